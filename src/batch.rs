@@ -0,0 +1,214 @@
+//! Generates a focused graph for each class and subclass found in a file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fbx,
+    graphviz::{RenderFormat, Renderer},
+};
+
+/// Filter template focusing on objects of the class and the subclass.
+///
+/// Variables `class`, `subclass` and `show_implicit_nodes` are set per graph.
+const TEMPLATE: &str = include_str!("../eachnode/template.json");
+/// Filter template focusing on the implicit root node.
+const TEMPLATE_ROOT: &str = include_str!("../eachnode/template_root.json");
+
+/// Variants of each graph: output subdirectory and `show_implicit_nodes`.
+const VARIANTS: &[(&str, bool)] = &[("explicit", false), ("with_anonymous", true)];
+
+/// Batch generation settings.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    /// Output directory.
+    pub out_dir: PathBuf,
+    /// Renderer and format of images written besides dot files.
+    pub render: Option<(Renderer, RenderFormat)>,
+}
+
+/// Graph focused on some objects.
+struct Target {
+    /// File stem of the outputs.
+    stem: String,
+    /// Description shown in the index.
+    title: String,
+    /// Number of the focused objects.
+    count: usize,
+    template: (&'static str, &'static str),
+    variables: BTreeMap<String, String>,
+}
+
+impl Batch {
+    /// Writes graphs of the root and of each class and subclass present in
+    /// the graph, and `index.html` listing them.
+    pub fn run(&self, graph: &fbx::Graph) -> io::Result<()> {
+        let targets = targets(graph);
+        for &(variant, show_implicit_nodes) in VARIANTS {
+            let dir = self.out_dir.join(variant);
+            fs::create_dir_all(&dir)?;
+            for target in &targets {
+                self.write_target(graph, target, show_implicit_nodes, &dir)?;
+            }
+        }
+        let mut index = BufWriter::new(File::create(self.out_dir.join("index.html"))?);
+        self.write_index(graph, &targets, &mut index)?;
+        index.flush()
+    }
+
+    /// Returns the extension of the files linked from the index.
+    fn extension(&self) -> &'static str {
+        match self.render {
+            Some((_, format)) => format.as_str(),
+            None => "dot",
+        }
+    }
+
+    fn write_target(
+        &self,
+        graph: &fbx::Graph,
+        target: &Target,
+        show_implicit_nodes: bool,
+        dir: &Path,
+    ) -> io::Result<()> {
+        let mut variables = target.variables.clone();
+        variables.insert(
+            "show_implicit_nodes".to_owned(),
+            show_implicit_nodes.to_string(),
+        );
+        let (template_name, template) = target.template;
+        let filters =
+            fbx::check::load_filter_source(Path::new(template_name), template, &variables)
+                .map_err(|diagnostics| {
+                    let messages = diagnostics
+                        .iter()
+                        .map(|diagnostic| diagnostic.to_string())
+                        .collect::<Vec<_>>();
+                    io::Error::other(format!(
+                        "built-in filter template is invalid: {}",
+                        messages.join("; ")
+                    ))
+                })?;
+
+        let mut graph = graph.clone();
+        filters.apply(&mut graph);
+        let mut dot_src = Vec::new();
+        graph.output_visible_nodes(&mut dot_src, show_implicit_nodes)?;
+        fs::write(dir.join(format!("{}.dot", target.stem)), &dot_src)?;
+        if let Some((ref renderer, format)) = self.render {
+            let out_path = dir.join(format!("{}.{}", target.stem, format));
+            renderer.render(&dot_src, format, &out_path)?;
+        }
+        Ok(())
+    }
+
+    fn write_index<W: Write>(
+        &self,
+        graph: &fbx::Graph,
+        targets: &[Target],
+        out: &mut W,
+    ) -> io::Result<()> {
+        let title = escape_html(&graph.name.display().to_string());
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>")?;
+        writeln!(out, "<head>")?;
+        writeln!(out, "<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>{}</title>", title)?;
+        writeln!(out, "</head>")?;
+        writeln!(out, "<body>")?;
+        writeln!(out, "<h1>{}</h1>", title)?;
+        writeln!(out, "<table>")?;
+        write!(out, "<tr><th>Objects</th><th>Count</th>")?;
+        for (variant, _) in VARIANTS {
+            write!(out, "<th>{}</th>", variant)?;
+        }
+        writeln!(out, "</tr>")?;
+        for target in targets {
+            write!(
+                out,
+                "<tr><td>{}</td><td>{}</td>",
+                escape_html(&target.title),
+                target.count
+            )?;
+            for (variant, _) in VARIANTS {
+                let href = format!("{}/{}.{}", variant, target.stem, self.extension());
+                write!(
+                    out,
+                    "<td><a href=\"{}\">{}</a></td>",
+                    escape_html(&href),
+                    self.extension()
+                )?;
+            }
+            writeln!(out, "</tr>")?;
+        }
+        writeln!(out, "</table>")?;
+        writeln!(out, "</body>")?;
+        writeln!(out, "</html>")?;
+        Ok(())
+    }
+}
+
+/// Returns the root and each pair of class and subclass present in the graph.
+fn targets(graph: &fbx::Graph) -> Vec<Target> {
+    let mut pairs = BTreeMap::<(&str, &str), usize>::new();
+    for props in graph.nodes.values().filter_map(|node| node.data.as_ref()) {
+        *pairs.entry((&props.class, &props.subclass)).or_default() += 1;
+    }
+
+    let mut stems = BTreeSet::new();
+    let root = Target {
+        stem: "root".to_owned(),
+        title: "(root)".to_owned(),
+        count: 1,
+        template: ("eachnode/template_root.json", TEMPLATE_ROOT),
+        variables: BTreeMap::new(),
+    };
+    stems.insert(root.stem.clone());
+    let mut targets = vec![root];
+    for ((class, subclass), count) in pairs {
+        let mut stem = if subclass.is_empty() {
+            file_stem(class)
+        } else {
+            format!("{}_{}", file_stem(class), file_stem(subclass))
+        };
+        // Different names can be sanitized into the same stem.
+        while !stems.insert(stem.clone()) {
+            stem.push('_');
+        }
+        let mut variables = BTreeMap::new();
+        variables.insert("class".to_owned(), class.to_owned());
+        variables.insert("subclass".to_owned(), subclass.to_owned());
+        targets.push(Target {
+            stem,
+            title: format!("{}::{}", class, subclass),
+            count,
+            template: ("eachnode/template.json", TEMPLATE),
+            variables,
+        });
+    }
+    targets
+}
+
+/// Replaces characters other than ASCII alphanumerics, `-` and `_`.
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,839 @@
+//! Validation of filter files.
+//!
+//! Problems are reported with their JSON paths (such as
+//! `node_filters[0].operations[1]`), and with line and column numbers if the
+//! source text is available.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::fbx::{
+    filter::{DegreeCondition, EdgeFilterCondition, Filters, NodeFilterCondition},
+    operation::{parse_operation, EdgeOperation, NodeOperation},
+    variable,
+};
+
+/// A problem in a filter file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Filter file.
+    pub file: PathBuf,
+    /// JSON path of the problematic value.
+    pub path: JsonPath,
+    /// Line and column numbers (1-based) of the value, if known.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if !self.path.0.is_empty() {
+            write!(f, ": `{}`", self.path)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Component of a JSON path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Path to a value in a JSON document.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JsonPath(pub Vec<Segment>);
+
+impl JsonPath {
+    /// Returns the path to the member of the object.
+    pub fn key(&self, key: &str) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Key(key.to_owned()));
+        path
+    }
+
+    /// Returns the path to the element of the array.
+    pub fn index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Index(index));
+        path
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) => {
+                    let mut chars = key.chars();
+                    let is_plain = chars
+                        .next()
+                        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+                    if is_plain {
+                        if i != 0 {
+                            f.write_str(".")?;
+                        }
+                        f.write_str(key)?;
+                    } else {
+                        write!(f, "[{}]", serde_json::Value::from(&key[..]))?;
+                    }
+                }
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format of filter files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl FilterFormat {
+    /// Returns the format by the extension of the path (JSON by default).
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("yaml") | Some("yml") => FilterFormat::Yaml,
+            Some("toml") => FilterFormat::Toml,
+            _ => FilterFormat::Json,
+        }
+    }
+
+    /// Deserializes the source, returning the position and the message on
+    /// error.
+    fn parse<T: DeserializeOwned>(
+        self,
+        source: &str,
+    ) -> Result<T, (Option<(usize, usize)>, String)> {
+        match self {
+            FilterFormat::Json => serde_json::from_str(source).map_err(|e| {
+                let position = Some((e.line(), e.column())).filter(|&(line, _)| line > 0);
+                let suffix = format!(" at line {} column {}", e.line(), e.column());
+                (position, strip_suffix(e.to_string(), &suffix))
+            }),
+            FilterFormat::Yaml => serde_yaml::from_str(source).map_err(|e| match e.location() {
+                Some(loc) => {
+                    let suffix = format!(" at line {} column {}", loc.line(), loc.column());
+                    (
+                        Some((loc.line(), loc.column())),
+                        strip_suffix(e.to_string(), &suffix),
+                    )
+                }
+                None => (None, e.to_string()),
+            }),
+            FilterFormat::Toml => toml::from_str(source).map_err(|e| {
+                let position = e.span().map(|span| line_column(source, span.start));
+                (position, e.message().to_owned())
+            }),
+        }
+    }
+}
+
+/// Removes the position appended to messages of parser errors.
+fn strip_suffix(message: String, suffix: &str) -> String {
+    match message.strip_suffix(suffix) {
+        Some(stripped) => stripped.to_owned(),
+        None => message,
+    }
+}
+
+/// Loads filter files with their includes, merges them in order, and
+/// validates them.
+///
+/// The format is chosen by the extension: `.yaml` or `.yml` for YAML, `.toml`
+/// for TOML, and JSON otherwise. Variables in the files are substituted by
+/// `variables`, or by defaults in the files. All problems found are returned,
+/// including syntax errors. Problems in YAML and TOML files are reported
+/// without line numbers, except for syntax errors.
+pub fn load_filters(
+    files: &[PathBuf],
+    variables: &BTreeMap<String, String>,
+) -> Result<Filters, Vec<Diagnostic>> {
+    let mut loader = Loader::default();
+    for file in files {
+        loader.load(file);
+    }
+    loader.finish(variables)
+}
+
+/// Loads filters from the source, such as a built-in filter.
+///
+/// `path` is used for diagnostics, the format, and includes, as
+/// [`load_filters`].
+pub fn load_filter_source(
+    path: &Path,
+    source: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<Filters, Vec<Diagnostic>> {
+    let mut loader = Loader::default();
+    loader.add(path, source.to_owned());
+    loader.finish(variables)
+}
+
+/// Part of a filter file needed before substituting variables.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Header {
+    #[serde(alias = "extends")]
+    include: Vec<PathBuf>,
+    variables: BTreeMap<String, String>,
+}
+
+/// Loaded filter file.
+struct FilterFile {
+    path: PathBuf,
+    source: String,
+    format: FilterFormat,
+    value: serde_json::Value,
+    header: Header,
+}
+
+impl FilterFile {
+    /// Substitutes variables and deserializes the filters.
+    ///
+    /// Invalid operations are removed before deserializing, so that the rest
+    /// of the file is still deserialized and validated. Returns the filters if
+    /// they are deserialized, and the problems found.
+    fn deserialize(
+        mut self,
+        variables: &BTreeMap<String, String>,
+    ) -> (Self, Option<Filters>, Vec<Diagnostic>) {
+        let mut problems = Vec::new();
+        let substituted = variable::substitute(&mut self.value, variables, &mut problems);
+        let invalid_operations = remove_invalid_operations(&mut self.value);
+        // Deserialize from the source if possible, to get the positions of
+        // errors.
+        let filters = if substituted || !invalid_operations.is_empty() {
+            Filters::deserialize(&self.value).map_err(|e| (None, e.to_string()))
+        } else {
+            self.format.parse::<Filters>(&self.source)
+        };
+        problems.extend(invalid_operations);
+        let mut diagnostics = self.located(problems);
+        let filters = match filters {
+            Ok(filters) => Some(filters),
+            Err((position, message)) => {
+                diagnostics.push(Diagnostic {
+                    file: self.path.clone(),
+                    path: JsonPath::default(),
+                    position,
+                    message,
+                });
+                None
+            }
+        };
+        (self, filters, diagnostics)
+    }
+
+    /// Returns diagnostics of the problems, with positions if possible.
+    fn located(&self, problems: Vec<(JsonPath, String)>) -> Vec<Diagnostic> {
+        problems
+            .into_iter()
+            .map(|(path, message)| Diagnostic {
+                file: self.path.clone(),
+                position: Some(self.format)
+                    .filter(|&format| format == FilterFormat::Json)
+                    .and_then(|_| locate(&self.source, &path.0))
+                    .map(|offset| line_column(&self.source, offset)),
+                path,
+                message,
+            })
+            .collect()
+    }
+}
+
+/// Loader of filter files and their includes.
+#[derive(Default)]
+struct Loader {
+    /// Loaded files, in the order to be merged.
+    files: Vec<FilterFile>,
+    /// Canonical paths of the files being loaded or already loaded.
+    visited: HashSet<PathBuf>,
+    /// Canonical paths of the files being loaded.
+    stack: Vec<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader {
+    /// Loads the file after the files it includes.
+    ///
+    /// Each file is loaded only once.
+    fn load(&mut self, file: &Path) {
+        // Files given explicitly can also be included by other files.
+        if let Ok(canonical) = fs::canonicalize(file) {
+            if self.visited.contains(&canonical) {
+                return;
+            }
+        }
+        match fs::read_to_string(file) {
+            Ok(source) => self.add(file, source),
+            Err(e) => self.diagnostics.push(Diagnostic {
+                file: file.to_owned(),
+                path: JsonPath::default(),
+                position: None,
+                message: format!("cannot read the file: {}", e),
+            }),
+        }
+    }
+
+    /// Loads the source of the file after the files it includes.
+    fn add(&mut self, file: &Path, source: String) {
+        let diagnostic = |position, message: String| Diagnostic {
+            file: file.to_owned(),
+            path: JsonPath::default(),
+            position,
+            message,
+        };
+        let format = FilterFormat::from_path(file);
+        let parsed = format.parse(&source).and_then(|value: serde_json::Value| {
+            let header = Header::deserialize(&value).map_err(|e| (None, e.to_string()))?;
+            Ok((value, header))
+        });
+        let file = match parsed {
+            Ok((value, header)) => FilterFile {
+                path: file.to_owned(),
+                source,
+                format,
+                value,
+                header,
+            },
+            Err((position, message)) => {
+                self.diagnostics.push(diagnostic(position, message));
+                return;
+            }
+        };
+
+        let canonical = fs::canonicalize(&file.path).unwrap_or_else(|_| file.path.clone());
+        self.visited.insert(canonical.clone());
+        self.stack.push(canonical);
+        let base_dir = file
+            .path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_owned();
+        for (i, include) in file.header.include.iter().enumerate() {
+            let include_path = JsonPath::default().key("include").index(i);
+            let included = base_dir.join(include);
+            let problem = match fs::canonicalize(&included) {
+                Ok(canonical) if self.stack.contains(&canonical) => {
+                    Some(format!("`{}` is included recursively", include.display()))
+                }
+                Ok(canonical) if self.visited.contains(&canonical) => None,
+                Ok(_) => {
+                    self.load(&included);
+                    None
+                }
+                Err(e) => Some(format!("cannot include `{}`: {}", include.display(), e)),
+            };
+            if let Some(problem) = problem {
+                self.diagnostics
+                    .extend(file.located(vec![(include_path, problem)]));
+            }
+        }
+        self.stack.pop();
+        self.files.push(file);
+    }
+
+    /// Substitutes variables, merges the loaded files and validates them.
+    fn finish(self, variables: &BTreeMap<String, String>) -> Result<Filters, Vec<Diagnostic>> {
+        if !self.diagnostics.is_empty() {
+            return Err(self.diagnostics);
+        }
+
+        // Defaults are overridden by later files, and by the given variables.
+        let mut defaults = BTreeMap::new();
+        for file in &self.files {
+            defaults.extend(file.header.variables.clone());
+        }
+        defaults.extend(variables.clone());
+        let variables = defaults;
+
+        let mut files = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut all_deserialized = true;
+        for file in self.files {
+            let (file, filters, file_diagnostics) = file.deserialize(&variables);
+            diagnostics.extend(file_diagnostics);
+            match filters {
+                Some(filters) => files.push((file, filters)),
+                None => all_deserialized = false,
+            }
+        }
+
+        let mut merged = Filters::default();
+        for (_, filters) in &files {
+            merged.merge(filters.clone());
+        }
+        // Operations are referred across files, so they are checked against the
+        // merged ones, only if all of the files are deserialized.
+        let scope = Some(&merged).filter(|_| all_deserialized);
+        for (file, filters) in &files {
+            diagnostics.extend(file.located(validate(filters, scope)));
+        }
+        if diagnostics.is_empty() {
+            Ok(merged)
+        } else {
+            Err(diagnostics)
+        }
+    }
+}
+
+/// Returns problems of the filters with their paths.
+///
+/// Operations referred by the filters are looked up in `scope`, which is
+/// usually the filters themselves or the merged ones. They are not checked if
+/// `scope` is `None`.
+pub fn validate(filters: &Filters, scope: Option<&Filters>) -> Vec<(JsonPath, String)> {
+    let mut problems = Vec::new();
+    let root = JsonPath::default();
+
+    for (i, filter) in filters.node_filters.iter().enumerate() {
+        let path = root.key("node_filters").index(i);
+        check_node_condition(&filter.condition, &path.key("condition"), &mut problems);
+        for (j, op_name) in filter.operations.iter().enumerate() {
+            if scope.is_some_and(|scope| !scope.node_operations.contains_key(op_name)) {
+                problems.push((
+                    path.key("operations").index(j),
+                    format!("undefined node operation `{}`", op_name),
+                ));
+            }
+        }
+    }
+    for (i, filter) in filters.edge_filters.iter().enumerate() {
+        let path = root.key("edge_filters").index(i);
+        check_edge_condition(&filter.condition, &path.key("condition"), &mut problems);
+        for (j, op_name) in filter.operations.iter().enumerate() {
+            if scope.is_some_and(|scope| !scope.edge_operations.contains_key(op_name)) {
+                problems.push((
+                    path.key("operations").index(j),
+                    format!("undefined edge operation `{}`", op_name),
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Checks that all operations can be parsed, before the whole filters are
+/// deserialized, so that all of the problems are reported with their paths.
+///
+/// Invalid operations are removed from the value.
+fn remove_invalid_operations(value: &mut serde_json::Value) -> Vec<(JsonPath, String)> {
+    let mut problems = Vec::new();
+    let root = JsonPath::default();
+    for key in &["node_operations", "edge_operations"] {
+        let ops = match value.get_mut(key).and_then(|ops| ops.as_object_mut()) {
+            Some(ops) => ops,
+            None => continue,
+        };
+        for (name, ops) in ops {
+            let ops = match ops.as_array_mut() {
+                Some(ops) => ops,
+                None => continue,
+            };
+            let mut i = 0;
+            ops.retain(|op| {
+                let result = if *key == "node_operations" {
+                    parse_operation::<NodeOperation>(op.clone()).map(drop)
+                } else {
+                    parse_operation::<EdgeOperation>(op.clone()).map(drop)
+                };
+                if let Err(ref e) = result {
+                    problems.push((root.key(key).key(name).index(i), e.clone()));
+                }
+                i += 1;
+                result.is_ok()
+            });
+        }
+    }
+    problems
+}
+
+fn check_regex(re: Option<&String>, path: JsonPath, problems: &mut Vec<(JsonPath, String)>) {
+    if let Some(Err(e)) = re.map(|re| Regex::new(re)) {
+        problems.push((path, format!("invalid regex: {}", e)));
+    }
+}
+
+fn check_node_condition(
+    cond: &NodeFilterCondition,
+    path: &JsonPath,
+    problems: &mut Vec<(JsonPath, String)>,
+) {
+    check_regex(cond.class.as_ref(), path.key("class"), problems);
+    check_regex(cond.subclass.as_ref(), path.key("subclass"), problems);
+    check_regex(cond.name.as_ref(), path.key("name"), problems);
+    check_regex(cond.uid.as_ref(), path.key("uid"), problems);
+    check_regex(
+        cond.via_connection_type.as_ref(),
+        path.key("via_connection_type"),
+        problems,
+    );
+    for (key, conds) in [("all", &cond.all), ("any", &cond.any)] {
+        for (i, cond) in conds.iter().flatten().enumerate() {
+            check_node_condition(cond, &path.key(key).index(i), problems);
+        }
+    }
+    let nested = [
+        ("not", &cond.not),
+        ("has_parent", &cond.has_parent),
+        ("has_child", &cond.has_child),
+        ("has_ancestor", &cond.has_ancestor),
+        ("has_descendant", &cond.has_descendant),
+    ];
+    for (key, cond) in nested {
+        if let Some(cond) = cond {
+            check_node_condition(cond, &path.key(key), problems);
+        }
+    }
+    for (key, cond) in [
+        ("in_degree", &cond.in_degree),
+        ("out_degree", &cond.out_degree),
+    ] {
+        if let Some(cond) = cond {
+            check_degree_condition(cond, &path.key(key), problems);
+        }
+    }
+}
+
+fn check_degree_condition(
+    cond: &DegreeCondition,
+    path: &JsonPath,
+    problems: &mut Vec<(JsonPath, String)>,
+) {
+    if let (Some(min), Some(max)) = (cond.min, cond.max) {
+        if min > max {
+            problems.push((
+                path.clone(),
+                format!("`min` ({}) is greater than `max` ({})", min, max),
+            ));
+        }
+    }
+    check_regex(
+        cond.connection_type.as_ref(),
+        path.key("connection_type"),
+        problems,
+    );
+    if let Some(ref cond) = cond.neighbor {
+        check_node_condition(cond, &path.key("neighbor"), problems);
+    }
+}
+
+fn check_edge_condition(
+    cond: &EdgeFilterCondition,
+    path: &JsonPath,
+    problems: &mut Vec<(JsonPath, String)>,
+) {
+    for (key, cond) in [
+        ("src_condition", &cond.src_condition),
+        ("dst_condition", &cond.dst_condition),
+    ] {
+        if let Some(cond) = cond {
+            check_node_condition(cond, &path.key(key), problems);
+        }
+    }
+    check_regex(
+        cond.connection_type.as_ref(),
+        path.key("connection_type"),
+        problems,
+    );
+    check_regex(
+        cond.property_name.as_ref(),
+        path.key("property_name"),
+        problems,
+    );
+    for (key, conds) in [("all", &cond.all), ("any", &cond.any)] {
+        for (i, cond) in conds.iter().flatten().enumerate() {
+            check_edge_condition(cond, &path.key(key).index(i), problems);
+        }
+    }
+    if let Some(ref cond) = cond.not {
+        check_edge_condition(cond, &path.key("not"), problems);
+    }
+}
+
+/// Returns the byte offset of the value at the path in the JSON source.
+///
+/// If the value does not exist, the offset of its nearest existing ascendant
+/// is returned. Returns `None` if the source is not valid JSON.
+fn locate(source: &str, path: &[Segment]) -> Option<usize> {
+    let mut scanner = Scanner {
+        source,
+        bytes: source.as_bytes(),
+        pos: 0,
+    };
+    scanner.find(path)
+}
+
+/// Returns 1-based line and column (in characters) of the byte offset.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Minimal JSON scanner to find values in the source text.
+struct Scanner<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes the byte (after whitespaces) if it is the expected one.
+    fn eat(&mut self, expected: u8) -> Option<()> {
+        self.skip_whitespace();
+        if self.peek()? == expected {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Finds the value at the path, starting from the value at the position.
+    fn find(&mut self, path: &[Segment]) -> Option<usize> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let (segment, rest) = match path.split_first() {
+            Some(v) => v,
+            None => return Some(start),
+        };
+        match (segment, self.peek()?) {
+            (Segment::Key(key), b'{') => {
+                self.pos += 1;
+                if self.eat(b'}').is_some() {
+                    return Some(start);
+                }
+                loop {
+                    self.skip_whitespace();
+                    let member = self.string()?;
+                    self.eat(b':')?;
+                    if member == *key {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    if self.eat(b',').is_none() {
+                        return Some(start);
+                    }
+                }
+            }
+            (&Segment::Index(index), b'[') => {
+                self.pos += 1;
+                if self.eat(b']').is_some() {
+                    return Some(start);
+                }
+                for i in 0.. {
+                    if i == index {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    if self.eat(b',').is_none() {
+                        break;
+                    }
+                }
+                Some(start)
+            }
+            _ => Some(start),
+        }
+    }
+
+    /// Reads a string literal.
+    fn string(&mut self) -> Option<String> {
+        let start = self.pos;
+        self.skip_value()?;
+        serde_json::from_str(&self.source[start..self.pos]).ok()
+    }
+
+    /// Skips a value.
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'"' => {
+                self.pos += 1;
+                loop {
+                    match self.peek()? {
+                        b'"' => break,
+                        b'\\' => self.pos += 2,
+                        _ => self.pos += 1,
+                    }
+                }
+                self.pos += 1;
+            }
+            b'{' | b'[' => {
+                self.pos += 1;
+                if self.eat(b'}').is_some() || self.eat(b']').is_some() {
+                    return Some(());
+                }
+                loop {
+                    self.skip_value()?;
+                    // Object members.
+                    if self.eat(b':').is_some() {
+                        self.skip_value()?;
+                    }
+                    if self.eat(b',').is_none() {
+                        break;
+                    }
+                }
+                self.skip_whitespace();
+                match self.peek()? {
+                    b'}' | b']' => self.pos += 1,
+                    _ => return None,
+                }
+            }
+            _ => {
+                while self
+                    .peek()
+                    .is_some_and(|b| !b",]}".contains(&b) && !b.is_ascii_whitespace())
+                {
+                    self.pos += 1;
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_json(source: &str) -> Result<Filters, Vec<Diagnostic>> {
+        load_filter_source(Path::new("filter.json"), source, &BTreeMap::new())
+    }
+
+    #[test]
+    fn unknown_top_level_fields() {
+        assert!(load_json(r#"{ "$schema": "../filter.schema.json" }"#).is_ok());
+        for source in [
+            r#"{ "node_filter": [] }"#,
+            r#"{ "show_implicit_node": true }"#,
+        ] {
+            let diagnostics = load_json(source).expect_err("should be rejected");
+            assert_eq!(diagnostics.len(), 1);
+            assert!(diagnostics[0].message.contains("unknown field"));
+        }
+    }
+
+    #[test]
+    fn all_problems_reported() {
+        let source = r#"{
+            "node_operations": {
+                "bad": [{ "op": "no-such-operation" }],
+                "good": [{ "op": "update-attr", "attrs": { "color": "red" } }]
+            },
+            "node_filters": [
+                { "condition": { "name": "${undefined}" }, "operations": ["bad"] },
+                { "condition": { "class": "(" }, "operations": ["good", "missing"] }
+            ]
+        }"#;
+        let diagnostics = load_json(source).expect_err("should be rejected");
+        let paths = diagnostics
+            .iter()
+            .map(|d| d.path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "node_filters[0].condition.name",
+                "node_operations.bad[0]",
+                "node_filters[1].condition.class",
+                "node_filters[1].operations[1]",
+            ]
+        );
+    }
+
+    /// Returns the source text from the located value to the end of the line.
+    fn located_at<'a>(source: &'a str, path: &[Segment]) -> &'a str {
+        let offset = locate(source, path).expect("should be valid JSON");
+        source[offset..].lines().next().unwrap_or("")
+    }
+
+    #[test]
+    fn locate_nested_paths() {
+        let source = r#"{
+            "node_operations": { "a": [1, { "op": "hide" }] },
+            "node_filters": [{}, { "condition": { "name": "x" } }]
+        }"#;
+        let key = |key: &str| Segment::Key(key.to_owned());
+        let path = [
+            key("node_operations"),
+            key("a"),
+            Segment::Index(1),
+            key("op"),
+        ];
+        assert_eq!(located_at(source, &path), r#""hide" }] },"#);
+        let path = [
+            key("node_filters"),
+            Segment::Index(1),
+            key("condition"),
+            key("name"),
+        ];
+        assert_eq!(located_at(source, &path), r#""x" } }]"#);
+        assert_eq!(locate(source, &[]), Some(0));
+    }
+
+    #[test]
+    fn locate_escaped_quotes() {
+        let source = r#"{ "a\"b": "c\"}", "a": ["\\", "d"] }"#;
+        let key = |key: &str| Segment::Key(key.to_owned());
+        assert_eq!(
+            located_at(source, &[key("a\"b")]),
+            r#""c\"}", "a": ["\\", "d"] }"#
+        );
+        assert_eq!(
+            located_at(source, &[key("a"), Segment::Index(1)]),
+            r#""d"] }"#
+        );
+    }
+
+    #[test]
+    fn locate_missing_paths() {
+        let source = r#"{ "a": { "b": [{}, []] }, "c": 1 }"#;
+        let key = |key: &str| Segment::Key(key.to_owned());
+        // The nearest existing ascendant is located.
+        assert_eq!(located_at(source, &[key("x")]), source);
+        let path = [key("a"), key("x"), key("y")];
+        assert_eq!(located_at(source, &path), r#"{ "b": [{}, []] }, "c": 1 }"#);
+        let path = [key("a"), key("b"), Segment::Index(5)];
+        assert_eq!(located_at(source, &path), r#"[{}, []] }, "c": 1 }"#);
+        let path = [key("a"), key("b"), Segment::Index(0), key("z")];
+        assert_eq!(located_at(source, &path), r#"{}, []] }, "c": 1 }"#);
+        let path = [key("a"), key("b"), Segment::Index(1), Segment::Index(0)];
+        assert_eq!(located_at(source, &path), r#"[]] }, "c": 1 }"#);
+        // Scalars have no members.
+        assert_eq!(located_at(source, &[key("c"), key("d")]), "1 }");
+        assert_eq!(locate(r#"{ "a": "#, &[key("a"), key("b")]), None);
+    }
+}
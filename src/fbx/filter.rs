@@ -1,23 +1,119 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+};
 
 use regex::{self, Regex};
+use schemars::JsonSchema;
 use serde::Deserialize;
 
-use crate::fbx::{Edge, Graph, Node};
+use crate::{
+    fbx::{
+        operation::{self, EdgeOperation, NodeOperation, OperationSchema, TargetKind},
+        template::{self, Captures},
+        Edge, Graph, Node, ObjectProperties,
+    },
+    graph::{
+        dot::{escape_label_text, is_esc_string_attr},
+        Adjacency, Traversal,
+    },
+};
 
-#[derive(Debug, Default, Clone, Deserialize)]
+/// Filters, loaded from a filter file.
+///
+/// All fields are optional.
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
 pub struct Filters {
-    pub graph_styles: HashMap<String, String>,
-    pub node_styles: HashMap<String, String>,
-    pub edge_styles: HashMap<String, String>,
+    /// Reference to the JSON Schema of the file, ignored.
+    #[serde(rename = "$schema")]
+    pub schema: Option<String>,
+    /// Graph attributes.
+    pub graph_styles: BTreeMap<String, String>,
+    /// Default node attributes.
+    pub node_styles: BTreeMap<String, String>,
+    /// Default edge attributes.
+    pub edge_styles: BTreeMap<String, String>,
+    /// Node operations keyed by names referred from `node_filters`.
+    #[serde(deserialize_with = "operation::deserialize_operations")]
+    #[schemars(with = "BTreeMap<String, Vec<OperationSchema<NodeOperation>>>")]
     pub node_operations: BTreeMap<String, Vec<NodeOperation>>,
+    /// Edge operations keyed by names referred from `edge_filters`.
+    #[serde(deserialize_with = "operation::deserialize_operations")]
+    #[schemars(with = "BTreeMap<String, Vec<OperationSchema<EdgeOperation>>>")]
     pub edge_operations: BTreeMap<String, Vec<EdgeOperation>>,
+    /// Node filters applied in order.
     pub node_filters: Vec<NodeFilter>,
+    /// Edge filters applied in order, after node filters.
     pub edge_filters: Vec<EdgeFilter>,
+    /// Whether to print nodes not defined in the file (`false` by default).
     pub show_implicit_nodes: Option<bool>,
+    /// Styles of clusters, keyed by cluster names.
+    pub cluster_styles: BTreeMap<String, BTreeMap<String, String>>,
+    /// Filter files merged before this file, relative to this file.
+    #[serde(alias = "extends")]
+    pub include: Vec<PathBuf>,
+    /// Default values of variables such as `${class}`, overridden by `--set`.
+    pub variables: BTreeMap<String, String>,
 }
 
 impl Filters {
+    /// Returns the JSON Schema (draft-07) of filter files.
+    pub fn json_schema() -> schemars::Schema {
+        schemars::generate::SchemaSettings::draft07()
+            .into_generator()
+            .into_root_schema_for::<Filters>()
+    }
+
+    /// Merges the filters loaded later.
+    ///
+    /// Styles and operations of `other` override ones with the same names,
+    /// filters of `other` are applied after the existing ones, and
+    /// `show_implicit_nodes` of `other` overrides the existing one if given.
+    pub fn merge(&mut self, other: Filters) {
+        self.graph_styles.extend(other.graph_styles);
+        self.node_styles.extend(other.node_styles);
+        self.edge_styles.extend(other.edge_styles);
+        for (cluster, styles) in other.cluster_styles {
+            self.cluster_styles
+                .entry(cluster)
+                .or_default()
+                .extend(styles);
+        }
+        self.node_operations.extend(other.node_operations);
+        self.edge_operations.extend(other.edge_operations);
+        self.node_filters.extend(other.node_filters);
+        self.edge_filters.extend(other.edge_filters);
+        if other.show_implicit_nodes.is_some() {
+            self.show_implicit_nodes = other.show_implicit_nodes;
+        }
+        self.include.extend(other.include);
+        self.variables.extend(other.variables);
+    }
+
+    /// Returns filters which color all objects by the palette, keyed by the
+    /// template.
+    pub fn palette(key: &str) -> Self {
+        let op_name = "palette".to_owned();
+        let mut filters = Filters::default();
+        filters.node_operations.insert(
+            op_name.clone(),
+            vec![NodeOperation::Palette {
+                key: key.to_owned(),
+                colors: BTreeMap::new(),
+            }],
+        );
+        filters.node_filters.push(NodeFilter {
+            // Matches any object, but not the root.
+            condition: NodeFilterCondition {
+                class: Some(String::new()),
+                ..Default::default()
+            },
+            operations: vec![op_name],
+        });
+        filters
+    }
+
     pub fn apply(&self, graph: &mut Graph) {
         for (name, value) in &self.node_styles {
             graph.node_styles.insert(name.clone(), value.clone());
@@ -28,7 +124,16 @@ impl Filters {
         for (name, value) in &self.graph_styles {
             graph.graph_styles.insert(name.clone(), value.clone());
         }
+        for (cluster, styles) in &self.cluster_styles {
+            let cluster_styles = graph.cluster_styles.entry(cluster.clone()).or_default();
+            for (name, value) in styles {
+                cluster_styles.insert(name.clone(), value.clone());
+            }
+        }
 
+        // Index of edges, rebuilt when operations change edges.
+        let mut adjacency = graph.adjacency();
+        let mut palette = Palette::of_graph(graph);
         {
             // Compile node filter conditions.
             let node_conditions = self
@@ -39,14 +144,25 @@ impl Filters {
                 .unwrap();
             // Apply each condition to all nodes.
             for &(ref cond, op_names) in &node_conditions {
-                let target_uids = graph
+                let targets = graph
                     .nodes
                     .iter()
-                    .filter(|&(_, node)| cond.is_match(node))
-                    .map(|(&uid, _)| uid)
+                    .filter_map(|(&uid, node)| Some((uid, cond.captures(node, graph, &adjacency)?)))
                     .collect::<Vec<_>>();
-                for uid in target_uids {
-                    self.apply_node_operations(uid, graph, op_names);
+                let mut deferred = DeferredOperations::default();
+                for (uid, captures) in targets {
+                    self.apply_node_operations(
+                        uid,
+                        &captures,
+                        graph,
+                        &mut adjacency,
+                        op_names,
+                        &mut deferred,
+                        &mut palette,
+                    );
+                }
+                if deferred.apply(graph) {
+                    adjacency = graph.adjacency();
                 }
             }
         }
@@ -60,70 +176,150 @@ impl Filters {
                 .unwrap();
             // Apply each condition to all edges.
             for &(ref cond, op_names) in &edge_conditions {
-                let (nodes, edges) = (&mut graph.nodes, &mut graph.edges);
-                let target_edges = edges
-                    .iter_mut()
-                    .filter(|edge| cond.is_match(edge, nodes))
+                let targets = graph
+                    .edges
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, edge)| Some((i, cond.captures(edge, graph, &adjacency)?)))
                     .collect::<Vec<_>>();
-                for target_edge in target_edges {
-                    self.apply_edge_operation(target_edge, nodes, op_names);
+                let (nodes, edges) = (&mut graph.nodes, &mut graph.edges);
+                for (i, captures) in targets {
+                    self.apply_edge_operation(&mut edges[i], &captures, nodes, op_names);
                 }
             }
         }
     }
 
-    fn apply_node_operations(&self, id: i64, graph: &mut Graph, ops: &[String]) {
+    #[allow(clippy::too_many_arguments)]
+    fn apply_node_operations(
+        &self,
+        id: i64,
+        captures: &Captures,
+        graph: &mut Graph,
+        adjacency: &mut Adjacency,
+        ops: &[String],
+        deferred: &mut DeferredOperations,
+        palette: &mut Palette,
+    ) {
         for ops in ops.iter().filter_map(|s| self.node_operations.get(s)) {
             for op in ops {
-                match op.name.as_ref() {
-                    "update-attr" => {
-                        for arg in &op.args {
-                            if arg.len() < 2 {
-                                continue;
+                let node = match graph.nodes.get(&id) {
+                    Some(node) => node,
+                    None => continue,
+                };
+                match op {
+                    NodeOperation::UpdateAttr { attrs } => {
+                        let attrs = expand_node_attrs(attrs, graph, node, captures);
+                        if let Some(node) = graph.nodes.get_mut(&id) {
+                            node.styles.extend(attrs);
+                        }
+                    }
+                    NodeOperation::RemoveAttr { attrs } => {
+                        if let Some(node) = graph.nodes.get_mut(&id) {
+                            for name in attrs {
+                                node.styles.remove(name);
                             }
-                            let name = arg[0].clone();
-                            let value = arg[1].clone();
+                        }
+                    }
+                    NodeOperation::SetLabel { template } => {
+                        let label = template::expand_node_label(template, graph, node, captures);
+                        if let Some(node) = graph.nodes.get_mut(&id) {
+                            node.styles.insert("label".to_owned(), label);
+                        }
+                    }
+                    NodeOperation::Cluster { name, styles } => {
+                        let cluster = template::expand_node(name, graph, node, captures);
+                        // Nodes are unclustered if the name is empty.
+                        let cluster = Some(cluster).filter(|cluster| !cluster.is_empty());
+                        if let Some(ref cluster) = cluster {
+                            let styles = expand_node_attrs(styles, graph, node, captures);
                             graph
-                                .nodes
-                                .get_mut(&id)
-                                .map(|n| n.styles.insert(name, value));
+                                .cluster_styles
+                                .entry(cluster.clone())
+                                .or_default()
+                                .extend(styles);
+                        }
+                        if let Some(node) = graph.nodes.get_mut(&id) {
+                            node.cluster = cluster;
                         }
                     }
-                    "remove-attr" => {
-                        if let Some(args) = op.args.get(0) {
-                            for name in args {
-                                graph.nodes.get_mut(&id).map(|n| n.styles.remove(name));
+                    NodeOperation::Collapse { by_class } => {
+                        deferred.add_collapse(graph, adjacency, id, *by_class);
+                    }
+                    NodeOperation::Palette { key, colors } => {
+                        let key = template::expand_node(key, graph, node, captures);
+                        // Nodes are not colored if the key is empty.
+                        if !key.is_empty() {
+                            let color = palette.color(&key, colors);
+                            if let Some(node) = graph.nodes.get_mut(&id) {
+                                let style = node.styles.entry("style".to_owned()).or_default();
+                                if !style.split(',').any(|s| s.trim() == "filled") {
+                                    if !style.is_empty() {
+                                        style.push(',');
+                                    }
+                                    style.push_str("filled");
+                                }
+                                node.styles.insert("fillcolor".to_owned(), color.clone());
+                                node.legend = Some((key, color));
                             }
                         }
                     }
-                    "hide" | "show" => {
-                        let visibility = op.name == "show";
-                        if let Some(args) = op.args.get(0) {
-                            for target in args {
-                                match target.as_ref() {
-                                    "self" => {
-                                        if let Some(n) = graph.nodes.get_mut(&id) {
-                                            n.visible = visibility;
-                                        }
-                                    }
-                                    "ascendant" => {
-                                        graph.map_ascendant(Some(id), |n| n.visible = visibility);
-                                    }
-                                    "descendant" => {
-                                        graph.map_descendant(Some(id), |n| n.visible = visibility);
-                                    }
-                                    "parents" => {
-                                        graph.map_parents(Some(id), |n| n.visible = visibility);
-                                    }
-                                    "children" => {
-                                        graph.map_children(Some(id), |n| n.visible = visibility);
+                    NodeOperation::Contract { styles } => {
+                        let styles = expand_node_attrs(styles, graph, node, captures);
+                        graph.contract(id, |from_parent, to_child| {
+                            let mut edge = Edge::new_with_data(
+                                from_parent.parent,
+                                to_child.child,
+                                to_child.data.clone(),
+                            );
+                            edge.styles = to_child.styles.clone();
+                            edge.visible = from_parent.visible && to_child.visible;
+                            edge.styles.extend(styles.iter().cloned());
+                            edge
+                        });
+                        *adjacency = graph.adjacency();
+                    }
+                    NodeOperation::Hide { targets } | NodeOperation::Show { targets } => {
+                        let visibility = matches!(op, NodeOperation::Show { .. });
+                        for target in targets {
+                            let follow = |e: &Edge| {
+                                connection_type_matches(target.connection_type.as_ref(), e)
+                            };
+                            let mut traversal = Traversal {
+                                max_depth: target.max_depth,
+                                follow: Some(&follow),
+                            };
+                            match target.kind {
+                                TargetKind::Self_ => {
+                                    if let Some(n) = graph.nodes.get_mut(&id) {
+                                        n.visible = visibility;
                                     }
-                                    _ => {}
+                                }
+                                TargetKind::Ascendant => {
+                                    graph.map_ascendant(Some(id), &traversal, adjacency, |n| {
+                                        n.visible = visibility
+                                    });
+                                }
+                                TargetKind::Descendant => {
+                                    graph.map_descendant(Some(id), &traversal, adjacency, |n| {
+                                        n.visible = visibility
+                                    });
+                                }
+                                TargetKind::Parents => {
+                                    traversal.max_depth = Some(1);
+                                    graph.map_ascendant(Some(id), &traversal, adjacency, |n| {
+                                        n.visible = visibility
+                                    });
+                                }
+                                TargetKind::Children => {
+                                    traversal.max_depth = Some(1);
+                                    graph.map_descendant(Some(id), &traversal, adjacency, |n| {
+                                        n.visible = visibility
+                                    });
                                 }
                             }
                         }
                     }
-                    _ => {}
                 }
             }
         }
@@ -132,60 +328,397 @@ impl Filters {
     fn apply_edge_operation(
         &self,
         edge: &mut Edge,
-        _nodes: &mut BTreeMap<i64, Node>,
+        captures: &Captures,
+        nodes: &mut BTreeMap<i64, Node>,
         ops: &[String],
     ) {
         for ops in ops.iter().filter_map(|s| self.edge_operations.get(s)) {
             for op in ops {
-                match op.name.as_ref() {
-                    "update-attr" => {
-                        for arg in &op.args {
-                            if arg.len() < 2 {
-                                continue;
-                            }
-                            let name = arg[0].clone();
-                            let value = arg[1].clone();
-                            edge.styles.insert(name, value);
+                match op {
+                    EdgeOperation::UpdateAttr { attrs } => {
+                        for (name, value) in attrs {
+                            let value = if is_esc_string_attr(name) {
+                                template::expand_edge_label(value, edge, nodes, captures)
+                            } else {
+                                template::expand_edge(value, edge, nodes, captures)
+                            };
+                            edge.styles.insert(name.clone(), value);
                         }
                     }
-                    "remove-attr" => {
-                        if let Some(args) = op.args.get(0) {
-                            for name in args {
-                                edge.styles.remove(name);
-                            }
+                    EdgeOperation::RemoveAttr { attrs } => {
+                        for name in attrs {
+                            edge.styles.remove(name);
                         }
                     }
-                    _ => {}
+                    EdgeOperation::Hide => edge.visible = false,
+                    EdgeOperation::Show => edge.visible = true,
+                    EdgeOperation::SetLabel { template } => {
+                        let label = template::expand_edge_label(template, edge, nodes, captures);
+                        edge.styles.insert("label".to_owned(), label);
+                    }
                 }
             }
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct NodeOperation {
-    pub name: String,
-    pub args: Vec<Vec<String>>,
+/// Expands templates of attribute values for the node.
+fn expand_node_attrs(
+    attrs: &BTreeMap<String, String>,
+    graph: &Graph,
+    node: &Node,
+    captures: &Captures,
+) -> Vec<(String, String)> {
+    attrs
+        .iter()
+        .map(|(key, value)| {
+            let value = expand_node_attr(key, value, graph, node, captures);
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// Expands the template of an attribute value for the node.
+///
+/// Variable values are escaped for escString attributes such as `label`.
+fn expand_node_attr(
+    key: &str,
+    template: &str,
+    graph: &Graph,
+    node: &Node,
+    captures: &Captures,
+) -> String {
+    if is_esc_string_attr(key) {
+        template::expand_node_label(template, graph, node, captures)
+    } else {
+        template::expand_node(template, graph, node, captures)
+    }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct EdgeOperation {
-    pub name: String,
-    pub args: Vec<Vec<String>>,
+/// Fill colors of palettes, clearly different from each other and light
+/// enough for black text.
+const PALETTE_COLORS: &[&str] = &[
+    "#8dd3c7", "#ff8066", "#ffff66", "#df80ff", "#52cc52", "#ffcccc", "#b8cc52", "#8095ff",
+    "#ccffff", "#80ff80", "#66ffff", "#cca3a3", "#ffbf66", "#c9e6a1", "#52adcc", "#bfb2ff",
+    "#ff80b5", "#ffffb2", "#66cc91", "#cc9952", "#66ffbf", "#bfff66", "#66ccff", "#a38fcc",
+];
+
+/// Colors of palette keys in a graph.
+struct Palette {
+    colors: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl Palette {
+    /// Returns the palette of the keys already colored in the graph, such as
+    /// by `--palette` before the filters.
+    fn of_graph(graph: &Graph) -> Self {
+        let colors = graph
+            .nodes
+            .values()
+            .filter_map(|node| node.legend.clone())
+            .collect();
+        Palette { colors }
+    }
+
+    /// Returns the color of the key.
+    ///
+    /// Keys in `pinned` get their colors. Other keys get colors of
+    /// [`PALETTE_COLORS`] not used by other keys in the graph, probing from
+    /// the one chosen by a hash of the key, so that a key usually gets the
+    /// same color in every file. Colors are shared only if all of them are
+    /// used.
+    fn color(&mut self, key: &str, pinned: &BTreeMap<String, String>) -> String {
+        if let Some(color) = pinned.get(key) {
+            self.colors.insert(key.to_owned(), color.clone());
+            return color.clone();
+        }
+        if let Some(color) = self.colors.get(key) {
+            return color.clone();
+        }
+        let used = self
+            .colors
+            .values()
+            .chain(pinned.values())
+            .map(|color| color.to_ascii_lowercase())
+            .collect::<HashSet<_>>();
+        // FNV-1a, which is stable across builds unlike `DefaultHasher`.
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        let start = (hash % PALETTE_COLORS.len() as u64) as usize;
+        let color = (0..PALETTE_COLORS.len())
+            .map(|i| PALETTE_COLORS[(start + i) % PALETTE_COLORS.len()])
+            .find(|color| !used.contains(*color))
+            .unwrap_or(PALETTE_COLORS[start])
+            .to_owned();
+        self.colors.insert(key.to_owned(), color.clone());
+        color
+    }
+}
+
+/// Node operations applied after all nodes matched by a filter are processed.
+#[derive(Default)]
+struct DeferredOperations {
+    /// Groups of nodes to be collapsed.
+    collapse: BTreeMap<CollapseKey, Vec<i64>>,
+}
+
+/// Nodes with the same key are collapsed into one node.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct CollapseKey {
+    /// Parent of the first edge to the node.
+    parent: i64,
+    /// Class and subclass, if grouped by them.
+    class: Option<(String, String)>,
+}
+
+impl DeferredOperations {
+    /// Adds the node to the group of its parent.
+    ///
+    /// A node with several parents is grouped by the parent of its first
+    /// edge (such as the `OO` connection to its owner in the file), and its
+    /// edges from the other parents are rerouted to the summary node.
+    fn add_collapse(&mut self, graph: &Graph, adjacency: &Adjacency, id: i64, by_class: bool) {
+        let parent = match graph.indexed_parent_edges(adjacency, id).next() {
+            Some(edge) => edge.parent,
+            None => return,
+        };
+        let class = if by_class {
+            let data = graph.nodes.get(&id).and_then(|n| n.data.as_ref());
+            Some(data.map_or_else(Default::default, |data| {
+                (data.class.clone(), data.subclass.clone())
+            }))
+        } else {
+            None
+        };
+        self.collapse
+            .entry(CollapseKey { parent, class })
+            .or_default()
+            .push(id);
+    }
+
+    /// Applies the operations, and returns whether edges are changed.
+    fn apply(self, graph: &mut Graph) -> bool {
+        let mut changed = false;
+        for mut members in self.collapse.into_values() {
+            // A node may be added more than once, or already collapsed into
+            // another group.
+            members.sort_unstable();
+            members.dedup();
+            members.retain(|id| graph.nodes.contains_key(id));
+            if members.len() < 2 {
+                continue;
+            }
+            let summary = collapse_summary(graph, &members);
+            graph.collapse(&members, summary);
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Creates a node summarizing the members.
+///
+/// The summary node gets a new id not used in the graph, and data with the
+/// class and the subclass shared by the members (or empty ones) and the number
+/// of the members as the name. Styles, the cluster and the legend entry are
+/// kept only if all the members have the same ones, and the summary is
+/// labelled with the number of the members.
+fn collapse_summary(graph: &Graph, members: &[i64]) -> Node {
+    let nodes = members
+        .iter()
+        .filter_map(|id| graph.nodes.get(id))
+        .collect::<Vec<_>>();
+    let first = nodes[0];
+    let class_of = |n: &Node| n.data.as_ref().map(|data| data.class.clone());
+    let subclass_of = |n: &Node| n.data.as_ref().map(|data| data.subclass.clone());
+    let shared = |f: &dyn Fn(&Node) -> Option<String>| {
+        let value = f(first);
+        if nodes.iter().all(|n| f(n) == value) {
+            value
+        } else {
+            None
+        }
+    };
+    let class = shared(&class_of);
+    let subclass = class.as_ref().and(shared(&subclass_of));
+
+    let id = graph.next_id();
+    let mut summary = Node::new_with_data(
+        id,
+        Some(ObjectProperties {
+            uid: id,
+            name: format!("{} objects", nodes.len()),
+            class: class.clone().unwrap_or_default(),
+            subclass: subclass.clone().unwrap_or_default(),
+        }),
+    );
+    summary.visible = nodes.iter().any(|n| n.is_visible());
+    if nodes.iter().all(|n| n.cluster == first.cluster) {
+        summary.cluster = first.cluster.clone();
+    }
+    if nodes.iter().all(|n| n.legend == first.legend) {
+        summary.legend = first.legend.clone();
+    }
+    summary.styles = first
+        .styles
+        .iter()
+        .filter(|&(name, value)| nodes.iter().all(|n| n.styles.get(name) == Some(value)))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    let label = match (&class, &subclass) {
+        (Some(class), Some(subclass)) => format!(
+            "{}\\n{}\\n× {}",
+            escape_label_text(class),
+            escape_label_text(subclass),
+            nodes.len()
+        ),
+        (Some(class), None) => format!("{}\\n× {}", escape_label_text(class), nodes.len()),
+        _ => format!("× {}", nodes.len()),
+    };
+    summary.styles.insert("label".to_owned(), label);
+    summary
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct NodeFilter {
     pub condition: NodeFilterCondition,
     pub operations: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct NodeFilterCondition {
     pub class: Option<String>,
     pub subclass: Option<String>,
     pub name: Option<String>,
     pub uid: Option<String>,
+    /// Matches if all of the conditions match.
+    pub all: Option<Vec<NodeFilterCondition>>,
+    /// Matches if any of the conditions matches.
+    pub any: Option<Vec<NodeFilterCondition>>,
+    /// Matches if the condition does not match.
+    pub not: Option<Box<NodeFilterCondition>>,
+    /// Matches if any parent matches the condition.
+    pub has_parent: Option<Box<NodeFilterCondition>>,
+    /// Matches if any child matches the condition.
+    pub has_child: Option<Box<NodeFilterCondition>>,
+    /// Matches if any ascendant matches the condition.
+    pub has_ancestor: Option<Box<NodeFilterCondition>>,
+    /// Matches if any descendant matches the condition.
+    pub has_descendant: Option<Box<NodeFilterCondition>>,
+    /// Connection types of the edges followed by `has_parent`, `has_child`,
+    /// `has_ancestor` and `has_descendant`.
+    pub via_connection_type: Option<String>,
+    /// Condition on the number of edges from parents.
+    pub in_degree: Option<DegreeCondition>,
+    /// Condition on the number of edges to children.
+    pub out_degree: Option<DegreeCondition>,
+}
+
+/// Condition on the number of edges connected to a node.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DegreeCondition {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+    /// Counts only edges with the matching connection type.
+    pub connection_type: Option<String>,
+    /// Counts only edges whose other end matches the condition.
+    pub neighbor: Option<Box<NodeFilterCondition>>,
+}
+
+impl DegreeCondition {
+    pub fn compile(&self) -> Result<CompiledDegreeCondition, regex::Error> {
+        let connection_type = if let Some(ref s) = self.connection_type {
+            Some(Regex::new(s)?)
+        } else {
+            None
+        };
+        let neighbor = if let Some(ref cond) = self.neighbor {
+            Some(Box::new(cond.compile()?))
+        } else {
+            None
+        };
+        Ok(CompiledDegreeCondition {
+            min: self.min,
+            max: self.max,
+            connection_type,
+            neighbor,
+        })
+    }
+}
+
+pub struct CompiledDegreeCondition {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+    pub connection_type: Option<Regex>,
+    pub neighbor: Option<Box<CompiledNodeFilterCondition>>,
+}
+
+impl CompiledDegreeCondition {
+    /// Returns whether the number of the given edges satisfies the condition.
+    ///
+    /// `neighbor_of` returns the other end of the edge.
+    fn is_match<'a, I>(
+        &self,
+        edges: I,
+        neighbor_of: fn(&Edge) -> i64,
+        graph: &Graph,
+        adjacency: &Adjacency,
+    ) -> bool
+    where
+        I: Iterator<Item = &'a Edge>,
+    {
+        let degree = edges
+            .filter(|e| connection_type_matches(self.connection_type.as_ref(), e))
+            .filter(|e| match self.neighbor {
+                Some(ref cond) => graph
+                    .nodes
+                    .get(&neighbor_of(e))
+                    .is_some_and(|n| cond.is_match(n, graph, adjacency)),
+                None => true,
+            })
+            .count();
+        self.min.is_none_or(|min| degree >= min) && self.max.is_none_or(|max| degree <= max)
+    }
+}
+
+/// Returns whether the connection type of the edge matches the regex.
+///
+/// Always returns `true` if no regex is given.
+fn connection_type_matches(re: Option<&Regex>, edge: &Edge) -> bool {
+    match (re, &edge.data.connection_type) {
+        (None, _) => true,
+        (Some(re), Some(con_type)) => re.is_match(con_type),
+        (Some(_), None) => false,
+    }
+}
+
+/// Matches the text against the regex, and adds its named capture groups.
+///
+/// Returns `Some(())` if no regex is given.
+fn capture(re: Option<&Regex>, text: &str, captures: &mut Captures) -> Option<()> {
+    let re = match re {
+        Some(re) => re,
+        None => return Some(()),
+    };
+    let caps = re.captures(text)?;
+    for name in re.capture_names().flatten() {
+        if let Some(m) = caps.name(name) {
+            captures.insert(name.to_owned(), m.as_str().to_owned());
+        }
+    }
+    Some(())
+}
+
+/// Adds captures which are not captured yet.
+fn merge_captures(captures: &mut Captures, other: Captures) {
+    for (name, value) in other {
+        captures.entry(name).or_insert(value);
+    }
 }
 
 impl NodeFilterCondition {
@@ -210,11 +743,68 @@ impl NodeFilterCondition {
         } else {
             None
         };
+        let all = self
+            .all
+            .iter()
+            .flatten()
+            .map(NodeFilterCondition::compile)
+            .collect::<Result<Vec<_>, _>>()?;
+        let any = if let Some(ref conds) = self.any {
+            Some(
+                conds
+                    .iter()
+                    .map(NodeFilterCondition::compile)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        } else {
+            None
+        };
+        let not = if let Some(ref cond) = self.not {
+            Some(Box::new(cond.compile()?))
+        } else {
+            None
+        };
+        let compile_boxed = |cond: &Option<Box<NodeFilterCondition>>| {
+            if let Some(ref cond) = *cond {
+                cond.compile().map(|c| Some(Box::new(c)))
+            } else {
+                Ok(None)
+            }
+        };
+        let has_parent = compile_boxed(&self.has_parent)?;
+        let has_child = compile_boxed(&self.has_child)?;
+        let has_ancestor = compile_boxed(&self.has_ancestor)?;
+        let has_descendant = compile_boxed(&self.has_descendant)?;
+        let via_connection_type = if let Some(ref s) = self.via_connection_type {
+            Some(Regex::new(s)?)
+        } else {
+            None
+        };
+        let in_degree = if let Some(ref cond) = self.in_degree {
+            Some(cond.compile()?)
+        } else {
+            None
+        };
+        let out_degree = if let Some(ref cond) = self.out_degree {
+            Some(cond.compile()?)
+        } else {
+            None
+        };
         Ok(CompiledNodeFilterCondition {
             class,
             subclass,
             name,
             uid,
+            all,
+            any,
+            not,
+            has_parent,
+            has_child,
+            has_ancestor,
+            has_descendant,
+            via_connection_type,
+            in_degree,
+            out_degree,
         })
     }
 }
@@ -224,50 +814,161 @@ pub struct CompiledNodeFilterCondition {
     pub subclass: Option<Regex>,
     pub name: Option<Regex>,
     pub uid: Option<Regex>,
+    pub all: Vec<CompiledNodeFilterCondition>,
+    pub any: Option<Vec<CompiledNodeFilterCondition>>,
+    pub not: Option<Box<CompiledNodeFilterCondition>>,
+    pub has_parent: Option<Box<CompiledNodeFilterCondition>>,
+    pub has_child: Option<Box<CompiledNodeFilterCondition>>,
+    pub has_ancestor: Option<Box<CompiledNodeFilterCondition>>,
+    pub has_descendant: Option<Box<CompiledNodeFilterCondition>>,
+    pub via_connection_type: Option<Regex>,
+    pub in_degree: Option<CompiledDegreeCondition>,
+    pub out_degree: Option<CompiledDegreeCondition>,
 }
 
 impl CompiledNodeFilterCondition {
-    pub fn is_match(&self, node: &Node) -> bool {
+    pub fn is_match(&self, node: &Node, graph: &Graph, adjacency: &Adjacency) -> bool {
+        self.captures(node, graph, adjacency).is_some()
+    }
+
+    /// Returns named capture groups if the node matches.
+    ///
+    /// Captures of the regexes directly in the condition take precedence over
+    /// ones of nested conditions.
+    pub fn captures(&self, node: &Node, graph: &Graph, adjacency: &Adjacency) -> Option<Captures> {
+        let mut captures = Captures::new();
         if let Some(ref data) = node.data {
-            if let Some(ref re) = self.class {
-                if !re.is_match(&data.class) {
-                    return false;
-                }
-            }
-            if let Some(ref re) = self.subclass {
-                if !re.is_match(&data.subclass) {
-                    return false;
-                }
+            capture(self.class.as_ref(), &data.class, &mut captures)?;
+            capture(self.subclass.as_ref(), &data.subclass, &mut captures)?;
+            capture(self.name.as_ref(), &data.name, &mut captures)?;
+        } else if self.class.is_some() || self.subclass.is_some() || self.name.is_some() {
+            return None;
+        }
+        capture(self.uid.as_ref(), &node.id.to_string(), &mut captures)?;
+        for cond in &self.all {
+            merge_captures(&mut captures, cond.captures(node, graph, adjacency)?);
+        }
+        if let Some(ref conds) = self.any {
+            let any = conds
+                .iter()
+                .find_map(|cond| cond.captures(node, graph, adjacency))?;
+            merge_captures(&mut captures, any);
+        }
+        if let Some(ref cond) = self.not {
+            if cond.is_match(node, graph, adjacency) {
+                return None;
             }
-            if let Some(ref re) = self.name {
-                if !re.is_match(&data.name) {
-                    return false;
-                }
+        }
+        merge_captures(
+            &mut captures,
+            self.topology_captures(node, graph, adjacency)?,
+        );
+        Some(captures)
+    }
+
+    /// Returns captures of the first registered node of the given ids which
+    /// matches.
+    fn any_captures<I: IntoIterator<Item = i64>>(
+        &self,
+        ids: I,
+        graph: &Graph,
+        adjacency: &Adjacency,
+    ) -> Option<Captures> {
+        ids.into_iter().find_map(|id| {
+            graph
+                .nodes
+                .get(&id)
+                .and_then(|n| self.captures(n, graph, adjacency))
+        })
+    }
+
+    fn topology_captures(
+        &self,
+        node: &Node,
+        graph: &Graph,
+        adjacency: &Adjacency,
+    ) -> Option<Captures> {
+        let mut captures = Captures::new();
+        let follow = |e: &Edge| connection_type_matches(self.via_connection_type.as_ref(), e);
+        let traversal = Traversal {
+            max_depth: None,
+            follow: Some(&follow),
+        };
+        if let Some(ref cond) = self.has_parent {
+            let parents = graph
+                .indexed_parent_edges(adjacency, node.id)
+                .filter(|e| follow(e))
+                .map(|e| e.parent);
+            merge_captures(&mut captures, cond.any_captures(parents, graph, adjacency)?);
+        }
+        if let Some(ref cond) = self.has_child {
+            let children = graph
+                .indexed_child_edges(adjacency, node.id)
+                .filter(|e| follow(e))
+                .map(|e| e.child);
+            merge_captures(
+                &mut captures,
+                cond.any_captures(children, graph, adjacency)?,
+            );
+        }
+        if let Some(ref cond) = self.has_ancestor {
+            let ascendants = graph.ascendants(Some(node.id), &traversal, adjacency);
+            merge_captures(
+                &mut captures,
+                cond.any_captures(ascendants, graph, adjacency)?,
+            );
+        }
+        if let Some(ref cond) = self.has_descendant {
+            let descendants = graph.descendants(Some(node.id), &traversal, adjacency);
+            merge_captures(
+                &mut captures,
+                cond.any_captures(descendants, graph, adjacency)?,
+            );
+        }
+        if let Some(ref cond) = self.in_degree {
+            if !cond.is_match(
+                graph.indexed_parent_edges(adjacency, node.id),
+                |e| e.parent,
+                graph,
+                adjacency,
+            ) {
+                return None;
             }
-        } else if self.class.is_some() || self.subclass.is_some() || self.name.is_some() {
-            return false;
         }
-        if let Some(ref re) = self.uid {
-            if !re.is_match(&node.id.to_string()) {
-                return false;
+        if let Some(ref cond) = self.out_degree {
+            if !cond.is_match(
+                graph.indexed_child_edges(adjacency, node.id),
+                |e| e.child,
+                graph,
+                adjacency,
+            ) {
+                return None;
             }
         }
-        true
+        Some(captures)
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct EdgeFilter {
     pub condition: EdgeFilterCondition,
     pub operations: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct EdgeFilterCondition {
     pub src_condition: Option<NodeFilterCondition>,
     pub dst_condition: Option<NodeFilterCondition>,
     pub connection_type: Option<String>,
     pub property_name: Option<String>,
+    /// Matches if all of the conditions match.
+    pub all: Option<Vec<EdgeFilterCondition>>,
+    /// Matches if any of the conditions matches.
+    pub any: Option<Vec<EdgeFilterCondition>>,
+    /// Matches if the condition does not match.
+    pub not: Option<Box<EdgeFilterCondition>>,
 }
 
 impl EdgeFilterCondition {
@@ -292,11 +993,35 @@ impl EdgeFilterCondition {
         } else {
             None
         };
+        let all = self
+            .all
+            .iter()
+            .flatten()
+            .map(EdgeFilterCondition::compile)
+            .collect::<Result<Vec<_>, _>>()?;
+        let any = if let Some(ref conds) = self.any {
+            Some(
+                conds
+                    .iter()
+                    .map(EdgeFilterCondition::compile)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        } else {
+            None
+        };
+        let not = if let Some(ref cond) = self.not {
+            Some(Box::new(cond.compile()?))
+        } else {
+            None
+        };
         Ok(CompiledEdgeFilterCondition {
             src_condition,
             dst_condition,
             connection_type,
             property_name,
+            all,
+            any,
+            not,
         })
     }
 }
@@ -306,46 +1031,162 @@ pub struct CompiledEdgeFilterCondition {
     pub dst_condition: Option<CompiledNodeFilterCondition>,
     pub connection_type: Option<Regex>,
     pub property_name: Option<Regex>,
+    pub all: Vec<CompiledEdgeFilterCondition>,
+    pub any: Option<Vec<CompiledEdgeFilterCondition>>,
+    pub not: Option<Box<CompiledEdgeFilterCondition>>,
 }
 
 impl CompiledEdgeFilterCondition {
-    pub fn is_match(&self, edge: &Edge, nodes: &BTreeMap<i64, Node>) -> bool {
-        if let Some(ref cond) = self.src_condition {
-            if let Some(src) = nodes.get(&edge.parent) {
-                if !cond.is_match(src) {
-                    return false;
-                }
-            } else {
-                return false;
-            }
+    pub fn is_match(&self, edge: &Edge, graph: &Graph, adjacency: &Adjacency) -> bool {
+        self.captures(edge, graph, adjacency).is_some()
+    }
+
+    /// Returns named capture groups if the edge matches.
+    ///
+    /// Captures of the regexes directly in the condition take precedence over
+    /// ones of nested conditions, and ones of `dst_condition` take precedence
+    /// over ones of `src_condition`.
+    pub fn captures(&self, edge: &Edge, graph: &Graph, adjacency: &Adjacency) -> Option<Captures> {
+        let mut captures = Captures::new();
+        if self.connection_type.is_some() {
+            let con_type = edge.data.connection_type.as_ref()?;
+            capture(self.connection_type.as_ref(), con_type, &mut captures)?;
+        }
+        if self.property_name.is_some() {
+            let prop_name = edge.data.property_name.as_ref()?;
+            capture(self.property_name.as_ref(), prop_name, &mut captures)?;
         }
         if let Some(ref cond) = self.dst_condition {
-            if let Some(dst) = nodes.get(&edge.child) {
-                if !cond.is_match(dst) {
-                    return false;
-                }
-            } else {
-                return false;
-            }
+            let dst = graph.nodes.get(&edge.child)?;
+            merge_captures(&mut captures, cond.captures(dst, graph, adjacency)?);
         }
-        if let Some(ref re) = self.connection_type {
-            if let Some(ref con_type) = edge.data.connection_type {
-                if !re.is_match(con_type) {
-                    return false;
-                }
-            } else {
-                return false;
+        if let Some(ref cond) = self.src_condition {
+            let src = graph.nodes.get(&edge.parent)?;
+            merge_captures(&mut captures, cond.captures(src, graph, adjacency)?);
+        }
+        for cond in &self.all {
+            merge_captures(&mut captures, cond.captures(edge, graph, adjacency)?);
+        }
+        if let Some(ref conds) = self.any {
+            let any = conds
+                .iter()
+                .find_map(|cond| cond.captures(edge, graph, adjacency))?;
+            merge_captures(&mut captures, any);
+        }
+        if let Some(ref cond) = self.not {
+            if cond.is_match(edge, graph, adjacency) {
+                return None;
             }
         }
-        if let Some(ref re) = self.property_name {
-            if let Some(ref prop_name) = edge.data.property_name {
-                if !re.is_match(prop_name) {
-                    return false;
-                }
-            } else {
-                return false;
+        Some(captures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a graph with nodes of the classes, whose uids start from 1.
+    fn graph_of_classes(classes: &[&str]) -> Graph {
+        let mut graph = Graph::new("test");
+        for (id, class) in (1..).zip(classes) {
+            graph.add_node(Node::new_with_data(
+                id,
+                Some(ObjectProperties {
+                    uid: id,
+                    name: "Cube".to_owned(),
+                    class: class.to_string(),
+                    subclass: "Mesh".to_owned(),
+                }),
+            ));
+        }
+        graph
+    }
+
+    fn label_after(ops: &str) -> String {
+        let filters: Filters = serde_json::from_str(&format!(
+            r#"{{
+                "node_operations": {{ "label": {} }},
+                "node_filters": [{{ "condition": {{}}, "operations": ["label"] }}]
+            }}"#,
+            ops
+        ))
+        .expect("should be valid filters");
+        let mut graph = graph_of_classes(&["Model"]);
+        filters.apply(&mut graph);
+        graph.nodes[&1].styles["label"].clone()
+    }
+
+    #[test]
+    fn legacy_update_attr_is_verbatim() {
+        let legacy = r#"[{ "name": "update-attr", "args": [["label", "{{in|out}|{name}}"]] }]"#;
+        assert_eq!(label_after(legacy), "{{in|out}|{name}}");
+        let tagged = r#"[{ "op": "update-attr", "attrs": { "label": "{{in|out}}|{name}" } }]"#;
+        assert_eq!(label_after(tagged), "{in|out}|Cube");
+        let set_label = r#"[{ "name": "set-label", "args": [["{class}::{name}"]] }]"#;
+        assert_eq!(label_after(set_label), "Model::Cube");
+    }
+
+    #[test]
+    fn palette_colors_are_distinct() {
+        let classes = [
+            "AnimationCurve",
+            "AnimationCurveNode",
+            "AnimationLayer",
+            "AnimationStack",
+            "BindingTable",
+            "CollectionExclusive",
+            "Deformer",
+            "DisplayLayer",
+            "Geometry",
+            "Implementation",
+            "LayeredTexture",
+            "Material",
+            "Model",
+            "NodeAttribute",
+            "Pose",
+            "SelectionSet",
+            "SubDeformer",
+            "Texture",
+            "Video",
+        ];
+        let mut graph = graph_of_classes(&classes);
+        Filters::palette(operation::DEFAULT_PALETTE_KEY).apply(&mut graph);
+        let colors = graph
+            .nodes
+            .values()
+            .map(|node| node.legend.clone().expect("should be colored").1)
+            .collect::<Vec<_>>();
+        let rgb = |color: &str| {
+            let channel = |i| i32::from_str_radix(&color[i..i + 2], 16).expect("should be hex");
+            [channel(1), channel(3), channel(5)]
+        };
+        for (i, a) in colors.iter().enumerate() {
+            for b in &colors[..i] {
+                let distance: i32 = rgb(a)
+                    .iter()
+                    .zip(&rgb(b))
+                    .map(|(a, b)| (a - b).pow(2))
+                    .sum();
+                assert!(distance >= 48 * 48, "{} and {} are too close", a, b);
             }
         }
-        true
+    }
+
+    #[test]
+    fn palette_colors_are_pinned() {
+        let mut graph = graph_of_classes(&["Model", "Geometry"]);
+        let filters: Filters = serde_json::from_str(
+            r##"{
+                "node_operations": {
+                    "palette": [{ "op": "palette", "colors": { "Model": "#8dd3c7" } }]
+                },
+                "node_filters": [{ "condition": {}, "operations": ["palette"] }]
+            }"##,
+        )
+        .expect("should be valid filters");
+        filters.apply(&mut graph);
+        assert_eq!(graph.nodes[&1].styles["fillcolor"], "#8dd3c7");
+        assert_ne!(graph.nodes[&2].styles["fillcolor"], "#8dd3c7");
     }
 }
@@ -0,0 +1,81 @@
+//! Shows only the neighborhood of focused objects.
+
+use std::{collections::BTreeSet, str::FromStr};
+
+use regex::Regex;
+
+use crate::{
+    fbx::{Graph, Node},
+    graph::Traversal,
+};
+
+/// Pattern of focused objects.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Object with the uid.
+    Uid(i64),
+    /// Objects with names matching the regex.
+    Name(Regex),
+}
+
+impl FromStr for Pattern {
+    type Err = regex::Error;
+
+    /// Parses a uid if it is an integer, or a regex of names otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<i64>() {
+            Ok(uid) => Ok(Pattern::Uid(uid)),
+            Err(_) => Ok(Pattern::Name(Regex::new(s)?)),
+        }
+    }
+}
+
+impl Pattern {
+    fn is_match(&self, node: &Node) -> bool {
+        match self {
+            Pattern::Uid(uid) => node.id == *uid,
+            Pattern::Name(re) => node
+                .data
+                .as_ref()
+                .is_some_and(|data| re.is_match(&data.name)),
+        }
+    }
+}
+
+/// Focused objects and the distances of ascendants and descendants shown.
+#[derive(Debug, Clone)]
+pub struct Focus {
+    /// Objects matching any of the patterns are focused.
+    pub patterns: Vec<Pattern>,
+    /// Distance of ascendants shown.
+    pub up: usize,
+    /// Distance of descendants shown.
+    pub down: usize,
+}
+
+impl Focus {
+    /// Hides objects other than the focused ones and their ascendants and
+    /// descendants within the distances.
+    ///
+    /// Objects already hidden (by filters) are kept hidden. Distances are
+    /// counted on all edges, including hidden ones.
+    pub fn apply(&self, graph: &mut Graph) {
+        let focused = graph
+            .nodes
+            .values()
+            .filter(|node| self.patterns.iter().any(|p| p.is_match(node)))
+            .map(|node| node.id)
+            .collect::<BTreeSet<_>>();
+        let traversal = |max_depth| Traversal {
+            max_depth: Some(max_depth),
+            follow: None,
+        };
+        let adjacency = graph.adjacency();
+        let mut shown = graph.ascendants(focused.iter().cloned(), &traversal(self.up), &adjacency);
+        shown.extend(graph.descendants(focused.iter().cloned(), &traversal(self.down), &adjacency));
+        shown.extend(focused);
+        for node in graph.nodes.values_mut() {
+            node.visible &= shown.contains(&node.id);
+        }
+    }
+}
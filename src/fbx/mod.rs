@@ -1,12 +1,29 @@
-use std::io::Read;
+use std::{error, fmt, io::Read};
 
-use fbxcel::pull_parser::any::AnyParser;
+use fbxcel::pull_parser::{
+    self,
+    any::{self, AnyParser},
+    ParserVersion,
+};
+
+use crate::graph::dot::escape_label_text;
 
 pub use self::property::ObjectProperties;
 
+pub mod check;
 pub mod filter;
+pub mod focus;
+pub mod neo4j;
+pub mod operation;
+pub mod path;
 mod property;
+pub mod template;
+pub mod tree;
 pub mod v7400;
+pub mod variable;
+
+/// Uid of the implicit root node.
+pub const ROOT_UID: i64 = 0;
 
 pub type NodeData = Option<ObjectProperties>;
 
@@ -14,16 +31,48 @@ pub type Graph = crate::graph::Graph<NodeData, EdgeData>;
 pub type Node = crate::graph::Node<NodeData>;
 pub type Edge = crate::graph::Edge<EdgeData>;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct EdgeData {
     pub connection_type: Option<String>,
     pub property_name: Option<String>,
 }
 
-pub fn traverse(graph: &mut Graph, src: impl Read) {
-    match AnyParser::from_reader(src).expect("Failed to create FBX parser") {
-        AnyParser::V7400(parser) => v7400::traverse(graph, parser),
-        parser => panic!("Unknown parser version: {:?}", parser.parser_version()),
+impl EdgeData {
+    /// Returns whether the edge is an object-object (`OO`) connection.
+    pub fn is_object_object(&self) -> bool {
+        self.connection_type.as_ref().map(|s| &s[..]) == Some("OO")
+    }
+}
+
+/// Error of reading an FBX file.
+#[derive(Debug)]
+pub enum TraverseError {
+    /// The header is invalid or of an unsupported version.
+    Header(any::Error),
+    /// The parser of the version is not supported.
+    UnsupportedParser(ParserVersion),
+    /// The content is invalid.
+    Parse(pull_parser::Error),
+}
+
+impl fmt::Display for TraverseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraverseError::Header(e) => e.fmt(f),
+            TraverseError::UnsupportedParser(version) => {
+                write!(f, "unsupported parser version: {:?}", version)
+            }
+            TraverseError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for TraverseError {}
+
+pub fn traverse(graph: &mut Graph, src: impl Read) -> Result<(), TraverseError> {
+    match AnyParser::from_reader(src).map_err(TraverseError::Header)? {
+        AnyParser::V7400(parser) => v7400::traverse(graph, parser).map_err(TraverseError::Parse),
+        parser => Err(TraverseError::UnsupportedParser(parser.parser_version())),
     }
 }
 
@@ -31,7 +80,10 @@ pub fn create_object_node(obj_props: &ObjectProperties) -> Node {
     let mut node = Node::new_with_data(obj_props.uid, Some(obj_props.clone()));
     let label = format!(
         "{}::{}\\n{}\\n{}",
-        obj_props.class, obj_props.name, obj_props.subclass, obj_props.uid
+        escape_label_text(&obj_props.class),
+        escape_label_text(&obj_props.name),
+        escape_label_text(&obj_props.subclass),
+        obj_props.uid
     );
     node.styles.insert("label".to_string(), label);
     node
@@ -0,0 +1,226 @@
+//! Exports graphs for Neo4j, as Cypher statements or neo4j-admin CSV files.
+//!
+//! Every node and relationship carries a `file` property (and CSV node IDs are
+//! prefixed by it), so that graphs of many FBX files can coexist in a single
+//! database.
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::fbx::{Edge, Graph, Node};
+
+/// Label given to all exported nodes.
+const OBJECT_LABEL: &str = "Object";
+/// Relationship type used for connections without connection type.
+const DEFAULT_RELATIONSHIP_TYPE: &str = "CONNECTED";
+
+/// Writes `CREATE` statements for the visible part of the graph.
+pub fn write_cypher<W: Write>(
+    graph: &Graph,
+    file_id: &str,
+    print_unregistered_nodes: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let file = cypher_string(file_id);
+    writeln!(
+        out,
+        "CREATE INDEX IF NOT EXISTS FOR (n:{}) ON (n.file, n.uid);",
+        cypher_name(OBJECT_LABEL)
+    )?;
+    for node in graph.nodes.values().filter(|n| n.is_visible()) {
+        let labels = node_labels(Some(node));
+        write!(
+            out,
+            "CREATE (:{} {{file: {}, uid: {}",
+            labels.join(":"),
+            file,
+            node.id
+        )?;
+        if let Some(ref data) = node.data {
+            write!(
+                out,
+                ", name: {}, class: {}, subclass: {}",
+                cypher_string(&data.name),
+                cypher_string(&data.class),
+                cypher_string(&data.subclass)
+            )?;
+        }
+        writeln!(out, "}});")?;
+    }
+    for uid in unregistered_nodes(graph, print_unregistered_nodes) {
+        writeln!(
+            out,
+            "CREATE (:{} {{file: {}, uid: {}}});",
+            node_labels(None).join(":"),
+            file,
+            uid
+        )?;
+    }
+    let object = cypher_name(OBJECT_LABEL);
+    for edge in graph.visible_edges(print_unregistered_nodes) {
+        write!(
+            out,
+            "MATCH (p:{obj} {{file: {file}, uid: {parent}}}), (c:{obj} {{file: {file}, uid: {child}}}) \
+             CREATE (p)-[:{ty} {{file: {file}",
+            obj = object,
+            file = file,
+            parent = edge.parent,
+            child = edge.child,
+            ty = cypher_name(relationship_type(edge))
+        )?;
+        if let Some(ref prop_name) = edge.data.property_name {
+            write!(out, ", property_name: {}", cypher_string(prop_name))?;
+        }
+        writeln!(out, "}}]->(c);")?;
+    }
+    Ok(())
+}
+
+/// Writes `nodes.csv` and `relationships.csv` for `neo4j-admin database import`
+/// into the given directory.
+pub fn write_csv(
+    graph: &Graph,
+    file_id: &str,
+    print_unregistered_nodes: bool,
+    dir: &Path,
+) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut nodes = BufWriter::new(File::create(dir.join("nodes.csv"))?);
+    writeln!(nodes, "id:ID,file,uid:long,name,class,subclass,:LABEL")?;
+    for node in graph.nodes.values().filter(|n| n.is_visible()) {
+        let (name, class, subclass) = match node.data {
+            Some(ref data) => (&data.name[..], &data.class[..], &data.subclass[..]),
+            None => ("", "", ""),
+        };
+        writeln!(
+            nodes,
+            "{},{},{},{},{},{},{}",
+            csv_field(&csv_node_id(file_id, node.id)),
+            csv_field(file_id),
+            node.id,
+            csv_field(name),
+            csv_field(class),
+            csv_field(subclass),
+            csv_field(&csv_labels(Some(node)))
+        )?;
+    }
+    for uid in unregistered_nodes(graph, print_unregistered_nodes) {
+        writeln!(
+            nodes,
+            "{},{},{},,,,{}",
+            csv_field(&csv_node_id(file_id, uid)),
+            csv_field(file_id),
+            uid,
+            csv_field(&csv_labels(None))
+        )?;
+    }
+    nodes.flush()?;
+
+    let mut relationships = BufWriter::new(File::create(dir.join("relationships.csv"))?);
+    writeln!(relationships, ":START_ID,:END_ID,:TYPE,file,property_name")?;
+    for edge in graph.visible_edges(print_unregistered_nodes) {
+        writeln!(
+            relationships,
+            "{},{},{},{},{}",
+            csv_field(&csv_node_id(file_id, edge.parent)),
+            csv_field(&csv_node_id(file_id, edge.child)),
+            csv_field(&csv_label(relationship_type(edge))),
+            csv_field(file_id),
+            csv_field(edge.data.property_name.as_ref().map_or("", |s| &s[..]))
+        )?;
+    }
+    relationships.flush()?;
+    Ok(())
+}
+
+/// Returns uids referred by visible edges but not registered as nodes.
+fn unregistered_nodes(graph: &Graph, print_unregistered_nodes: bool) -> BTreeSet<i64> {
+    graph
+        .visible_edges(print_unregistered_nodes)
+        .flat_map(|e| vec![e.parent, e.child])
+        .filter(|uid| !graph.nodes.contains_key(uid))
+        .collect()
+}
+
+/// Returns labels of the node, quoted for Cypher.
+///
+/// `None` means the node is not registered (i.e. implicit).
+fn node_labels(node: Option<&Node>) -> Vec<String> {
+    raw_node_labels(node)
+        .iter()
+        .map(|label| cypher_name(label))
+        .collect()
+}
+
+fn raw_node_labels(node: Option<&Node>) -> Vec<&str> {
+    let mut labels = vec![OBJECT_LABEL];
+    match node.map(|n| &n.data) {
+        Some(Some(data)) => {
+            labels.push(&data.class);
+            if !data.subclass.is_empty() && data.subclass != data.class {
+                labels.push(&data.subclass);
+            }
+        }
+        Some(None) => labels.push("Root"),
+        None => labels.push("Implicit"),
+    }
+    labels.retain(|label| !label.is_empty());
+    labels
+}
+
+fn relationship_type(edge: &Edge) -> &str {
+    match edge.data.connection_type {
+        Some(ref ty) if !ty.is_empty() => ty,
+        _ => DEFAULT_RELATIONSHIP_TYPE,
+    }
+}
+
+/// Quotes a label or relationship type for Cypher.
+fn cypher_name(raw: &str) -> String {
+    format!("`{}`", raw.replace('`', "``"))
+}
+
+/// Quotes a string literal for Cypher.
+fn cypher_string(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len() + 2);
+    escaped.push('"');
+    for c in raw.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_node_id(file_id: &str, uid: i64) -> String {
+    format!("{}:{}", file_id, uid)
+}
+
+/// Removes characters which cannot be used in labels of neo4j-admin CSV.
+fn csv_label(raw: &str) -> String {
+    raw.replace(';', "_")
+}
+
+fn csv_labels(node: Option<&Node>) -> String {
+    raw_node_labels(node)
+        .iter()
+        .map(|label| csv_label(label))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn csv_field(raw: &str) -> String {
+    format!("\"{}\"", raw.replace('"', "\"\""))
+}
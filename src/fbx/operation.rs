@@ -0,0 +1,547 @@
+//! Operations applied to nodes and edges matched by filters.
+//!
+//! Operations are written as objects tagged by `op`, such as
+//! `{"op": "update-attr", "attrs": {"color": "red"}}`. The legacy form
+//! `{"name": "update-attr", "args": [["color", "red"]]}` is also accepted.
+
+use std::{collections::BTreeMap, convert::TryFrom, fmt};
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::fbx::template;
+
+/// Key template of `palette` operation used if none is given.
+pub const DEFAULT_PALETTE_KEY: &str = "{class}";
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum NodeOperation {
+    /// Sets attributes, whose values are templates.
+    UpdateAttr { attrs: BTreeMap<String, String> },
+    /// Removes attributes.
+    RemoveAttr { attrs: Vec<String> },
+    /// Sets the label by the template.
+    SetLabel { template: String },
+    /// Puts the node into the cluster named by the template.
+    Cluster {
+        name: String,
+        /// Styles of the cluster, whose values are templates.
+        #[serde(default)]
+        styles: BTreeMap<String, String>,
+    },
+    /// Merges nodes sharing the same parent into a summary node.
+    Collapse {
+        /// Groups nodes also by class and subclass.
+        #[serde(default)]
+        by_class: bool,
+    },
+    /// Removes the node and connects its parents and children.
+    Contract {
+        /// Styles of the new edges, whose values are templates.
+        #[serde(default)]
+        styles: BTreeMap<String, String>,
+    },
+    /// Hides the targets (such as `self` or `descendant:2`).
+    Hide {
+        #[schemars(with = "Vec<String>")]
+        targets: Vec<TraversalTarget>,
+    },
+    /// Shows the targets (such as `self` or `descendant:2`).
+    Show {
+        #[schemars(with = "Vec<String>")]
+        targets: Vec<TraversalTarget>,
+    },
+    /// Fills nodes with colors keyed by the template.
+    Palette {
+        #[serde(default = "default_palette_key")]
+        key: String,
+        /// Colors of some keys, pinned in every file.
+        #[serde(default)]
+        colors: BTreeMap<String, String>,
+    },
+}
+
+/// Target of `show` and `hide` node operations.
+///
+/// Written as `kind[:max_depth[:connection_type]]`, such as `descendant:2` or
+/// `ascendant::^OO$`. The depth is ignored for `self`, `parents` and
+/// `children`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TraversalTarget {
+    pub kind: TargetKind,
+    pub max_depth: Option<usize>,
+    pub connection_type: Option<Regex>,
+}
+
+/// Kind of [`TraversalTarget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Self_,
+    Ascendant,
+    Descendant,
+    Parents,
+    Children,
+}
+
+impl TargetKind {
+    /// Kinds with their names.
+    const NAMES: &'static [(&'static str, TargetKind)] = &[
+        ("self", TargetKind::Self_),
+        ("ascendant", TargetKind::Ascendant),
+        ("descendant", TargetKind::Descendant),
+        ("parents", TargetKind::Parents),
+        ("children", TargetKind::Children),
+    ];
+}
+
+impl TryFrom<String> for TraversalTarget {
+    type Error = String;
+
+    fn try_from(target: String) -> Result<Self, Self::Error> {
+        let mut parts = target.splitn(3, ':');
+        let kind = parts.next().unwrap_or_default();
+        let kind = TargetKind::NAMES
+            .iter()
+            .find(|&&(name, _)| name == kind)
+            .map(|&(_, kind)| kind)
+            .ok_or_else(|| {
+                let names = TargetKind::NAMES.iter().map(|&(name, _)| name);
+                format!(
+                    "unknown target kind `{}` (expected one of {})",
+                    kind,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            })?;
+        let max_depth = parts
+            .next()
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse()
+                    .map_err(|_| format!("invalid traversal depth `{}`", s))
+            })
+            .transpose()?;
+        let connection_type = parts
+            .next()
+            .filter(|s| !s.is_empty())
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("invalid regex: {}", e))?;
+        Ok(TraversalTarget {
+            kind,
+            max_depth,
+            connection_type,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum EdgeOperation {
+    /// Sets attributes, whose values are templates.
+    UpdateAttr { attrs: BTreeMap<String, String> },
+    /// Removes attributes.
+    RemoveAttr { attrs: Vec<String> },
+    /// Sets the label by the template.
+    SetLabel { template: String },
+    /// Hides the edge.
+    Hide,
+    /// Shows the edge.
+    Show,
+}
+
+fn default_palette_key() -> String {
+    DEFAULT_PALETTE_KEY.to_owned()
+}
+
+/// Operation in the legacy form, with string arguments.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct LegacyOperation {
+    /// Operation name, such as `update-attr`.
+    name: String,
+    /// Arguments, in the order of the fields of the tagged form.
+    #[serde(default)]
+    args: Vec<Vec<String>>,
+}
+
+/// Operation in the tagged or the legacy form.
+// Used only for the JSON Schema.
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[schemars(rename = "{T}OrLegacy")]
+#[allow(dead_code)]
+pub enum OperationSchema<T> {
+    Tagged(T),
+    Legacy(LegacyOperation),
+}
+
+impl LegacyOperation {
+    /// Returns the first argument of the first row.
+    fn first_arg(&self, expected: &str) -> Result<String, String> {
+        self.args
+            .first()
+            .and_then(|row| row.first())
+            .cloned()
+            .ok_or_else(|| format!("`{}`: expected {} in `args[0][0]`", self.name, expected))
+    }
+
+    /// Returns the first row, which must not be empty.
+    fn first_row(&self, expected: &str) -> Result<Vec<String>, String> {
+        match self.args.first() {
+            Some(row) if !row.is_empty() => Ok(row.clone()),
+            _ => Err(format!(
+                "`{}`: expected {} in `args[0]`",
+                self.name, expected
+            )),
+        }
+    }
+
+    /// Returns the targets in `args[0]`.
+    fn targets(&self) -> Result<Vec<TraversalTarget>, String> {
+        self.first_row("targets")?
+            .into_iter()
+            .map(TraversalTarget::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("`{}`: {}", self.name, e))
+    }
+
+    /// Returns `[name, value]` rows from `skip` as a map.
+    fn attr_rows(&self, skip: usize) -> Result<BTreeMap<String, String>, String> {
+        self.args
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(i, row)| match &row[..] {
+                [name, value] => Ok((name.clone(), value.clone())),
+                _ => Err(format!(
+                    "`{}`: expected `[name, value]` in `args[{}]`, but got {} values",
+                    self.name,
+                    i,
+                    row.len()
+                )),
+            })
+            .collect()
+    }
+
+    /// Returns `[name, value]` rows of `update-attr` as a map.
+    ///
+    /// Values of the legacy form are not templates, so braces in them (such
+    /// as record labels) are escaped to be kept verbatim.
+    fn update_attr_rows(&self) -> Result<BTreeMap<String, String>, String> {
+        let rows = self.attr_rows(0)?;
+        Ok(rows
+            .into_iter()
+            .map(|(name, value)| (name, template::escape(&value)))
+            .collect())
+    }
+
+    fn unknown(&self, kind: &str) -> String {
+        format!("unknown {} operation `{}`", kind, self.name)
+    }
+}
+
+impl TryFrom<LegacyOperation> for NodeOperation {
+    type Error = String;
+
+    fn try_from(op: LegacyOperation) -> Result<Self, Self::Error> {
+        Ok(match op.name.as_ref() {
+            "update-attr" => NodeOperation::UpdateAttr {
+                attrs: op.update_attr_rows()?,
+            },
+            "remove-attr" => NodeOperation::RemoveAttr {
+                attrs: op.first_row("attribute names")?,
+            },
+            "set-label" => NodeOperation::SetLabel {
+                template: op.first_arg("a label template")?,
+            },
+            "cluster" => NodeOperation::Cluster {
+                name: op.first_arg("a cluster name template")?,
+                styles: op.attr_rows(1)?,
+            },
+            "collapse" => {
+                let options = op.args.first().cloned().unwrap_or_default();
+                if let Some(option) = options.iter().find(|o| *o != "parent" && *o != "class") {
+                    return Err(format!(
+                        "`collapse`: unknown option `{}` (expected `parent` or `class`)",
+                        option
+                    ));
+                }
+                NodeOperation::Collapse {
+                    by_class: options.iter().any(|o| o == "class"),
+                }
+            }
+            "contract" => NodeOperation::Contract {
+                styles: op.attr_rows(0)?,
+            },
+            "hide" => NodeOperation::Hide {
+                targets: op.targets()?,
+            },
+            "show" => NodeOperation::Show {
+                targets: op.targets()?,
+            },
+            "palette" => {
+                let key = match op.args.first().map(|row| &row[..]) {
+                    None => default_palette_key(),
+                    Some([key]) => key.clone(),
+                    Some(_) => return Err("`palette`: expected `[key]` in `args[0]`".to_owned()),
+                };
+                NodeOperation::Palette {
+                    key,
+                    colors: op.attr_rows(1)?,
+                }
+            }
+            _ => return Err(op.unknown("node")),
+        })
+    }
+}
+
+impl TryFrom<LegacyOperation> for EdgeOperation {
+    type Error = String;
+
+    fn try_from(op: LegacyOperation) -> Result<Self, Self::Error> {
+        Ok(match op.name.as_ref() {
+            "update-attr" => EdgeOperation::UpdateAttr {
+                attrs: op.update_attr_rows()?,
+            },
+            "remove-attr" => EdgeOperation::RemoveAttr {
+                attrs: op.first_row("attribute names")?,
+            },
+            "set-label" => EdgeOperation::SetLabel {
+                template: op.first_arg("a label template")?,
+            },
+            "hide" => EdgeOperation::Hide,
+            "show" => EdgeOperation::Show,
+            _ => return Err(op.unknown("edge")),
+        })
+    }
+}
+
+/// Deserializes operation lists keyed by names, accepting both of the tagged
+/// and the legacy forms.
+pub fn deserialize_operations<'de, D, T>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: de::DeserializeOwned + TryFrom<LegacyOperation, Error = String>,
+{
+    let raw = BTreeMap::<String, Vec<serde_json::Value>>::deserialize(deserializer)?;
+    raw.into_iter()
+        .map(|(name, ops)| {
+            let ops = ops
+                .into_iter()
+                .enumerate()
+                .map(|(i, op)| {
+                    parse_operation(op).map_err(|e| {
+                        de::Error::custom(OperationError {
+                            name: &name,
+                            index: i,
+                            e,
+                        })
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((name, ops))
+        })
+        .collect()
+}
+
+/// Parses an operation in either form.
+pub fn parse_operation<T>(op: serde_json::Value) -> Result<T, String>
+where
+    T: de::DeserializeOwned + TryFrom<LegacyOperation, Error = String>,
+{
+    let is_legacy = op.get("op").is_none() && op.get("name").is_some();
+    if is_legacy {
+        let op: LegacyOperation = serde_json::from_value(op).map_err(|e| e.to_string())?;
+        T::try_from(op)
+    } else {
+        serde_json::from_value(op).map_err(|e| e.to_string())
+    }
+}
+
+/// Error of an operation, with its location.
+struct OperationError<'a> {
+    name: &'a str,
+    index: usize,
+    e: String,
+}
+
+impl fmt::Display for OperationError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid operation `{}[{}]`: {}",
+            self.name, self.index, self.e
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::Path};
+
+    use super::*;
+    use crate::fbx::check;
+
+    /// Parses the operation in either form.
+    fn parse<T>(op: &str) -> Result<T, String>
+    where
+        T: de::DeserializeOwned + TryFrom<LegacyOperation, Error = String>,
+    {
+        parse_operation(serde_json::from_str(op).expect("should be valid JSON"))
+    }
+
+    /// Asserts that the legacy operation is converted to the tagged one.
+    fn assert_node_equivalent(legacy: &str, tagged: &str) {
+        let legacy = parse::<NodeOperation>(legacy).expect("legacy form should be valid");
+        let tagged = parse::<NodeOperation>(tagged).expect("tagged form should be valid");
+        // Regexes of targets are compared by their patterns.
+        assert_eq!(format!("{:?}", legacy), format!("{:?}", tagged));
+    }
+
+    fn assert_edge_equivalent(legacy: &str, tagged: &str) {
+        let legacy = parse::<EdgeOperation>(legacy).expect("legacy form should be valid");
+        let tagged = parse::<EdgeOperation>(tagged).expect("tagged form should be valid");
+        assert_eq!(format!("{:?}", legacy), format!("{:?}", tagged));
+    }
+
+    #[test]
+    fn legacy_node_operations() {
+        let cases = [
+            (
+                r#"{ "name": "update-attr", "args": [["color", "red"], ["penwidth", "2"]] }"#,
+                r#"{ "op": "update-attr", "attrs": { "color": "red", "penwidth": "2" } }"#,
+            ),
+            (
+                r#"{ "name": "remove-attr", "args": [["color", "label"]] }"#,
+                r#"{ "op": "remove-attr", "attrs": ["color", "label"] }"#,
+            ),
+            (
+                r#"{ "name": "set-label", "args": [["{class}::{name}"]] }"#,
+                r#"{ "op": "set-label", "template": "{class}::{name}" }"#,
+            ),
+            (
+                r#"{ "name": "cluster", "args": [["{top_model}"], ["style", "filled"]] }"#,
+                r#"{ "op": "cluster", "name": "{top_model}", "styles": { "style": "filled" } }"#,
+            ),
+            (r#"{ "name": "collapse" }"#, r#"{ "op": "collapse" }"#),
+            (
+                r#"{ "name": "collapse", "args": [["parent"]] }"#,
+                r#"{ "op": "collapse", "by_class": false }"#,
+            ),
+            (
+                r#"{ "name": "collapse", "args": [["parent", "class"]] }"#,
+                r#"{ "op": "collapse", "by_class": true }"#,
+            ),
+            (
+                r#"{ "name": "contract", "args": [["style", "dashed"]] }"#,
+                r#"{ "op": "contract", "styles": { "style": "dashed" } }"#,
+            ),
+            (
+                r#"{ "name": "hide", "args": [["self", "descendant:2:^OO$"]] }"#,
+                r#"{ "op": "hide", "targets": ["self", "descendant:2:^OO$"] }"#,
+            ),
+            (
+                r#"{ "name": "show", "args": [["ascendant::^OP$"]] }"#,
+                r#"{ "op": "show", "targets": ["ascendant::^OP$"] }"#,
+            ),
+            (r#"{ "name": "palette" }"#, r#"{ "op": "palette" }"#),
+            (
+                r##"{ "name": "palette", "args": [["{subclass}"], ["Mesh", "#8dd3c7"]] }"##,
+                r##"{ "op": "palette", "key": "{subclass}", "colors": { "Mesh": "#8dd3c7" } }"##,
+            ),
+        ];
+        for (legacy, tagged) in cases {
+            assert_node_equivalent(legacy, tagged);
+        }
+    }
+
+    #[test]
+    fn legacy_edge_operations() {
+        let cases = [
+            (
+                r#"{ "name": "update-attr", "args": [["color", "red"]] }"#,
+                r#"{ "op": "update-attr", "attrs": { "color": "red" } }"#,
+            ),
+            (
+                r#"{ "name": "remove-attr", "args": [["color"]] }"#,
+                r#"{ "op": "remove-attr", "attrs": ["color"] }"#,
+            ),
+            (
+                r#"{ "name": "set-label", "args": [["{property_name}"]] }"#,
+                r#"{ "op": "set-label", "template": "{property_name}" }"#,
+            ),
+            (r#"{ "name": "hide" }"#, r#"{ "op": "hide" }"#),
+            (r#"{ "name": "show" }"#, r#"{ "op": "show" }"#),
+        ];
+        for (legacy, tagged) in cases {
+            assert_edge_equivalent(legacy, tagged);
+        }
+    }
+
+    #[test]
+    fn invalid_legacy_operations() {
+        let cases = [
+            (
+                r#"{ "name": "update-attr", "args": [["color", "red"], ["penwidth"]] }"#,
+                "`update-attr`: expected `[name, value]` in `args[1]`, but got 1 values",
+            ),
+            (
+                r#"{ "name": "cluster", "args": [["{top_model}"], ["style", "filled", "x"]] }"#,
+                "`cluster`: expected `[name, value]` in `args[1]`, but got 3 values",
+            ),
+            (
+                r#"{ "name": "remove-attr", "args": [[]] }"#,
+                "`remove-attr`: expected attribute names in `args[0]`",
+            ),
+            (
+                r#"{ "name": "set-label" }"#,
+                "`set-label`: expected a label template in `args[0][0]`",
+            ),
+            (
+                r#"{ "name": "collapse", "args": [["parent", "subclass"]] }"#,
+                "`collapse`: unknown option `subclass` (expected `parent` or `class`)",
+            ),
+            (
+                r#"{ "name": "palette", "args": [["{class}", "{subclass}"]] }"#,
+                "`palette`: expected `[key]` in `args[0]`",
+            ),
+            (
+                r#"{ "name": "hide", "args": [["sef"]] }"#,
+                "`hide`: unknown target kind `sef` (expected one of self, ascendant, \
+                 descendant, parents, children)",
+            ),
+            (
+                r#"{ "name": "updte-attr" }"#,
+                "unknown node operation `updte-attr`",
+            ),
+        ];
+        for (op, message) in cases {
+            assert_eq!(parse::<NodeOperation>(op).unwrap_err(), message);
+        }
+        assert_eq!(
+            parse::<EdgeOperation>(r#"{ "name": "collapse" }"#).unwrap_err(),
+            "unknown edge operation `collapse`"
+        );
+    }
+
+    #[test]
+    fn examples_load() {
+        let pattern = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/*.json");
+        let examples = glob::glob(pattern.to_str().expect("should be UTF-8"))
+            .expect("should be a valid pattern")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("should be readable");
+        assert!(!examples.is_empty());
+        for example in examples {
+            if let Err(diagnostics) =
+                check::load_filters(std::slice::from_ref(&example), &BTreeMap::new())
+            {
+                panic!("{}: {:?}", example.display(), diagnostics);
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Finds connection paths between objects.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+};
+
+use regex::Regex;
+
+use crate::fbx::{tree::describe, Graph};
+
+/// Maximum number of partial paths searched for all paths.
+const SEARCH_BUDGET: usize = 1_000_000;
+
+/// Paths between two sets of objects.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Start objects of the query.
+    pub from: BTreeSet<i64>,
+    /// End objects of the query.
+    pub to: BTreeSet<i64>,
+    /// Whether the edges go from `to` to `from`.
+    pub reversed: bool,
+    /// Paths as indices of edges, in the direction of the edges.
+    pub paths: Vec<Vec<usize>>,
+    /// Whether the search stopped before finding all paths up to the limit.
+    pub truncated: bool,
+}
+
+/// Returns uids of objects with the uid or with names matching the regex.
+///
+/// The pattern is a uid if it is an integer, or a regex of names otherwise.
+pub fn find_objects(graph: &Graph, pattern: &str) -> Result<BTreeSet<i64>, regex::Error> {
+    if let Ok(uid) = pattern.parse::<i64>() {
+        return Ok(graph
+            .nodes
+            .keys()
+            .cloned()
+            .filter(|&id| id == uid)
+            .collect());
+    }
+    let re = Regex::new(pattern)?;
+    Ok(graph
+        .nodes
+        .values()
+        .filter(|node| {
+            node.data
+                .as_ref()
+                .is_some_and(|data| re.is_match(&data.name))
+        })
+        .map(|node| node.id)
+        .collect())
+}
+
+/// Finds paths from `from` to `to` following edges from parents to children,
+/// or from `to` to `from` if there are none.
+///
+/// Only the shortest path is found if `limit` is `None`, or the shortest paths
+/// up to the limit otherwise.
+pub fn find_paths(
+    graph: &Graph,
+    from: BTreeSet<i64>,
+    to: BTreeSet<i64>,
+    limit: Option<usize>,
+) -> Paths {
+    let search = |from: &BTreeSet<i64>, to: &BTreeSet<i64>| match limit {
+        Some(limit) => graph.all_paths(from, to, limit, SEARCH_BUDGET),
+        None => (graph.shortest_path(from, to).into_iter().collect(), false),
+    };
+    let (mut paths, mut truncated) = search(&from, &to);
+    let mut reversed = false;
+    if paths.is_empty() {
+        let (reversed_paths, reversed_truncated) = search(&to, &from);
+        paths = reversed_paths;
+        truncated |= reversed_truncated;
+        reversed = true;
+    }
+    Paths {
+        from,
+        to,
+        reversed,
+        paths,
+        truncated,
+    }
+}
+
+impl Paths {
+    /// Writes each path in a line, from `from` to `to`.
+    ///
+    /// Edges are written as `-[OO]->`, or as `<-[OO]-` if they go against the
+    /// query.
+    pub fn write_text<W: Write>(&self, graph: &Graph, out: &mut W) -> io::Result<()> {
+        for path in &self.paths {
+            let edges = path.iter().map(|&i| &graph.edges[i]).collect::<Vec<_>>();
+            let start = match (edges.first(), edges.last()) {
+                (Some(first), _) if !self.reversed => first.parent,
+                (_, Some(last)) => last.child,
+                // Empty paths are only for objects in both `from` and `to`.
+                _ => *self
+                    .from
+                    .intersection(&self.to)
+                    .next()
+                    .expect("empty path should start at a common object"),
+            };
+            write!(out, "{}", describe(graph, start))?;
+            let mut ordered = edges;
+            if self.reversed {
+                ordered.reverse();
+            }
+            for edge in ordered {
+                let connection = match (&edge.data.connection_type, &edge.data.property_name) {
+                    (Some(ty), Some(prop)) => format!("{}: {}", ty, prop),
+                    (Some(ty), None) => ty.clone(),
+                    (None, _) => String::new(),
+                };
+                let (arrow, next) = if self.reversed {
+                    (format!("<-[{}]-", connection), edge.parent)
+                } else {
+                    (format!("-[{}]->", connection), edge.child)
+                };
+                write!(out, " {} {}", arrow, describe(graph, next))?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Hides everything except the paths, and highlights them.
+    pub fn highlight(&self, graph: &mut Graph) {
+        let edges = self
+            .paths
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let mut nodes = self
+            .from
+            .intersection(&self.to)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for &i in &edges {
+            nodes.insert(graph.edges[i].parent);
+            nodes.insert(graph.edges[i].child);
+        }
+
+        for node in graph.nodes.values_mut() {
+            node.visible = nodes.contains(&node.id);
+            let is_end = self.from.contains(&node.id) || self.to.contains(&node.id);
+            if node.visible && is_end {
+                node.styles.insert("style".to_owned(), "filled".to_owned());
+                node.styles
+                    .insert("fillcolor".to_owned(), "#ffcccc".to_owned());
+            }
+        }
+        for (i, edge) in graph.edges.iter_mut().enumerate() {
+            edge.visible = edges.contains(&i);
+            if edge.visible {
+                edge.styles.insert("color".to_owned(), "#ff0000".to_owned());
+                edge.styles.insert("penwidth".to_owned(), "2".to_owned());
+            }
+        }
+    }
+}
@@ -2,7 +2,10 @@
 
 use std::io::Read;
 
-use fbxcel::pull_parser::v7400::{attribute::loaders::DirectLoader, Attributes as Attributes7400};
+use fbxcel::pull_parser::{
+    v7400::{attribute::loaders::DirectLoader, Attributes as Attributes7400},
+    Result,
+};
 
 #[derive(Debug, Clone)]
 pub struct ObjectProperties {
@@ -13,22 +16,28 @@ pub struct ObjectProperties {
 }
 
 impl ObjectProperties {
-    pub fn from_attrs7400<R: Read>(attrs: Attributes7400<'_, R>) -> Option<Self> {
+    /// Loads the properties, or returns `None` if the attributes are not of an
+    /// object.
+    pub fn from_attrs7400<R: Read>(attrs: Attributes7400<'_, R>) -> Result<Option<Self>> {
         let mut attrs = attrs.into_iter(std::iter::repeat(DirectLoader));
-        let uid = attrs.next()?.unwrap().get_i64()?;
-        let (name, class) = attrs
-            .next()?
-            .unwrap()
-            .get_string()
-            .and_then(separate_name_class)
-            .map(|(n, c)| (n.to_owned(), c.to_owned()))?;
-        let subclass = attrs.next()?.unwrap().get_string()?.to_owned();
+        let mut next = || attrs.next().transpose();
+        let uid = next()?.and_then(|attr| attr.get_i64());
+        let name_class = next()?;
+        let name_class = name_class
+            .as_ref()
+            .and_then(|attr| attr.get_string())
+            .and_then(separate_name_class);
+        let subclass = next()?;
+        let subclass = subclass.as_ref().and_then(|attr| attr.get_string());
 
-        Some(Self {
-            uid,
-            name,
-            class,
-            subclass,
+        Ok(match (uid, name_class, subclass) {
+            (Some(uid), Some((name, class)), Some(subclass)) => Some(Self {
+                uid,
+                name: name.to_owned(),
+                class: class.to_owned(),
+                subclass: subclass.to_owned(),
+            }),
+            _ => None,
         })
     }
 }
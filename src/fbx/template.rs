@@ -0,0 +1,216 @@
+//! Templates such as `{class}::{name}` used in filter operations.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use crate::{
+    fbx::{Edge, Graph, Node, ROOT_UID},
+    graph::dot::escape_label_text,
+};
+
+/// Values of named capture groups, by group names.
+pub type Captures = BTreeMap<String, String>;
+
+/// Expands `{key}` placeholders by `lookup`.
+///
+/// `{{` and `}}` are literal braces. Placeholders unknown to `lookup` are kept
+/// as is.
+pub fn expand<F>(template: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            expanded.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        if tail.starts_with('{') {
+            if let Some(end) = tail.find('}') {
+                let key = &tail[1..end];
+                match lookup(key) {
+                    Some(value) => expanded.push_str(&value),
+                    None => expanded.push_str(&tail[..=end]),
+                }
+                rest = &tail[end + 1..];
+                continue;
+            }
+        }
+        expanded.push_str(&tail[..1]);
+        rest = &tail[1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Escapes braces so that the text expands to itself.
+pub fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+/// Returns the value of a template variable for the node.
+///
+/// Available variables are `uid`, `class`, `name` and `subclass`.
+pub fn node_variable(node: &Node, key: &str) -> Option<String> {
+    match key {
+        "uid" => Some(node.id.to_string()),
+        "class" | "name" | "subclass" => {
+            let data = node.data.as_ref();
+            Some(
+                data.map(|data| match key {
+                    "class" => data.class.clone(),
+                    "name" => data.name.clone(),
+                    _ => data.subclass.clone(),
+                })
+                .unwrap_or_default(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Expands a template for the node.
+///
+/// Available variables are named capture groups of the matched condition,
+/// ones of [`node_variable`], and `top_model`, which is the name of the
+/// top-level `Model` (connected to the root) owning the node. Captures take
+/// precedence over the other variables.
+pub fn expand_node(template: &str, graph: &Graph, node: &Node, captures: &Captures) -> String {
+    expand(template, |key| {
+        captures
+            .get(key)
+            .cloned()
+            .or_else(|| graph_node_variable(graph, node, key))
+    })
+}
+
+/// Expands a template for the node into a label.
+///
+/// Variable values are escaped so that they are shown literally.
+pub fn expand_node_label(
+    template: &str,
+    graph: &Graph,
+    node: &Node,
+    captures: &Captures,
+) -> String {
+    expand(template, |key| {
+        captures
+            .get(key)
+            .cloned()
+            .or_else(|| graph_node_variable(graph, node, key))
+            .map(|v| escape_label_text(&v))
+    })
+}
+
+fn graph_node_variable(graph: &Graph, node: &Node, key: &str) -> Option<String> {
+    match key {
+        "top_model" => Some(
+            top_model(graph, node.id)
+                .and_then(|model| model.data.as_ref())
+                .map(|data| data.name.clone())
+                .unwrap_or_default(),
+        ),
+        _ => node_variable(node, key),
+    }
+}
+
+/// Returns the nearest top-level `Model` which is the node itself or its
+/// ascendant.
+fn top_model(graph: &Graph, id: i64) -> Option<&Node> {
+    let is_top_model = |node: &Node| {
+        node.data.as_ref().is_some_and(|data| data.class == "Model")
+            && graph
+                .parent_edges(node.id)
+                .any(|e| e.parent == ROOT_UID && e.data.is_object_object())
+    };
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(id);
+    while let Some(id) = queue.pop_front() {
+        if !visited.insert(id) {
+            continue;
+        }
+        if let Some(node) = graph.nodes.get(&id).filter(|node| is_top_model(node)) {
+            return Some(node);
+        }
+        queue.extend(
+            graph
+                .parent_edges(id)
+                .filter(|e| e.data.is_object_object())
+                .map(|e| e.parent),
+        );
+    }
+    None
+}
+
+/// Returns the value of a template variable for the edge.
+///
+/// Available variables are `connection_type`, `property_name`, and node
+/// variables of the ends prefixed by `parent.` or `child.` (such as
+/// `parent.class`). `parent` and `child` are the uids of the ends.
+pub fn edge_variable(edge: &Edge, nodes: &BTreeMap<i64, Node>, key: &str) -> Option<String> {
+    match key {
+        "connection_type" => Some(edge.data.connection_type.clone().unwrap_or_default()),
+        "property_name" => Some(edge.data.property_name.clone().unwrap_or_default()),
+        "parent" => Some(edge.parent.to_string()),
+        "child" => Some(edge.child.to_string()),
+        _ => {
+            let (uid, node_key) = if let Some(node_key) = key.strip_prefix("parent.") {
+                (edge.parent, node_key)
+            } else if let Some(node_key) = key.strip_prefix("child.") {
+                (edge.child, node_key)
+            } else {
+                return None;
+            };
+            match nodes.get(&uid) {
+                Some(node) => node_variable(node, node_key),
+                // Not registered.
+                None => match node_key {
+                    "uid" => Some(uid.to_string()),
+                    "class" | "name" | "subclass" => Some(String::new()),
+                    _ => None,
+                },
+            }
+        }
+    }
+}
+
+/// Expands a template for the edge.
+///
+/// Available variables are named capture groups of the matched condition and
+/// ones of [`edge_variable`]. Captures take precedence over the other
+/// variables.
+pub fn expand_edge(
+    template: &str,
+    edge: &Edge,
+    nodes: &BTreeMap<i64, Node>,
+    captures: &Captures,
+) -> String {
+    expand(template, |key| {
+        captures
+            .get(key)
+            .cloned()
+            .or_else(|| edge_variable(edge, nodes, key))
+    })
+}
+
+/// Expands a template for the edge into a label.
+///
+/// Variable values are escaped so that they are shown literally.
+pub fn expand_edge_label(
+    template: &str,
+    edge: &Edge,
+    nodes: &BTreeMap<i64, Node>,
+    captures: &Captures,
+) -> String {
+    expand(template, |key| {
+        captures
+            .get(key)
+            .cloned()
+            .or_else(|| edge_variable(edge, nodes, key))
+            .map(|v| escape_label_text(&v))
+    })
+}
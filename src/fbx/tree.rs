@@ -0,0 +1,105 @@
+//! Prints the object-object hierarchy as an indented text tree.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+use crate::fbx::{Graph, ROOT_UID};
+
+/// Writes the visible `OO` hierarchy below the document root.
+///
+/// Nodes with multiple parents are marked, and their subtrees are printed only
+/// at their first occurrence.
+pub fn write_tree<W: Write>(
+    graph: &Graph,
+    print_unregistered_nodes: bool,
+    max_depth: Option<usize>,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut children = HashMap::<i64, Vec<i64>>::new();
+    let mut parent_counts = HashMap::<i64, usize>::new();
+    for edge in graph
+        .visible_edges(print_unregistered_nodes)
+        .filter(|e| e.data.is_object_object())
+    {
+        children.entry(edge.parent).or_default().push(edge.child);
+        *parent_counts.entry(edge.child).or_default() += 1;
+    }
+
+    let printer = TreePrinter {
+        graph,
+        children,
+        parent_counts,
+        max_depth,
+    };
+    writeln!(out, "{}", describe(graph, ROOT_UID))?;
+    let mut printed = HashSet::new();
+    printed.insert(ROOT_UID);
+    printer.print_children(ROOT_UID, "", 0, &mut printed, out)
+}
+
+struct TreePrinter<'a> {
+    graph: &'a Graph,
+    /// Children of each node, in the connection order.
+    children: HashMap<i64, Vec<i64>>,
+    /// Number of parents of each node.
+    parent_counts: HashMap<i64, usize>,
+    max_depth: Option<usize>,
+}
+
+impl TreePrinter<'_> {
+    fn print_children<W: Write>(
+        &self,
+        uid: i64,
+        prefix: &str,
+        depth: usize,
+        printed: &mut HashSet<i64>,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let children = match self.children.get(&uid) {
+            Some(children) => children,
+            None => return Ok(()),
+        };
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            writeln!(out, "{}└── ... ({} children)", prefix, children.len())?;
+            return Ok(());
+        }
+        for (i, &child) in children.iter().enumerate() {
+            let is_last = i + 1 == children.len();
+            let (branch, child_prefix) = if is_last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            write!(out, "{}{}{}", prefix, branch, describe(self.graph, child))?;
+            let parents = self.parent_counts.get(&child).cloned().unwrap_or(0);
+            if parents > 1 {
+                write!(out, " [shared: {} parents]", parents)?;
+            }
+            if !printed.insert(child) {
+                if self.children.contains_key(&child) {
+                    write!(out, " (see above)")?;
+                }
+                writeln!(out)?;
+                continue;
+            }
+            writeln!(out)?;
+            let child_prefix = format!("{}{}", prefix, child_prefix);
+            self.print_children(child, &child_prefix, depth + 1, printed, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns a one-line description of the node.
+pub(crate) fn describe(graph: &Graph, uid: i64) -> String {
+    match graph.nodes.get(&uid).map(|n| &n.data) {
+        Some(Some(data)) => format!(
+            "{}::{} ({}) uid={}",
+            data.class, data.name, data.subclass, uid
+        ),
+        Some(None) if uid == ROOT_UID => format!("(root) uid={}", uid),
+        _ => format!("(implicit) uid={}", uid),
+    }
+}
@@ -1,79 +1,90 @@
 use std::io::Read;
 
-use crate::fbx::{create_object_node, Edge, Graph, ObjectProperties};
+use crate::{
+    fbx::{create_object_node, Edge, Graph, ObjectProperties},
+    graph::dot::escape_label_text,
+};
 
 use fbxcel::{
     low::v7400::AttributeValue,
-    pull_parser::v7400::{attribute::loaders::DirectLoader, Attributes, Event, Parser},
+    pull_parser::{
+        v7400::{attribute::loaders::DirectLoader, Attributes, Event, Parser},
+        Result,
+    },
 };
 
-pub fn traverse<R: Read>(graph: &mut Graph, mut parser: Parser<R>) {
+pub fn traverse<R: Read>(graph: &mut Graph, mut parser: Parser<R>) -> Result<()> {
     assert!(!parser.is_used());
     loop {
-        match parser.next_event().expect("Failed to parse") {
+        match parser.next_event()? {
             Event::StartNode(node) => match node.name() {
-                "Objects" => traverse_objects(graph, &mut parser),
-                "Connections" => traverse_connections(graph, &mut parser),
-                _ => parser.skip_current_node().unwrap(),
+                "Objects" => traverse_objects(graph, &mut parser)?,
+                "Connections" => traverse_connections(graph, &mut parser)?,
+                _ => parser.skip_current_node()?,
             },
             Event::EndNode => unreachable!(),
             Event::EndFbx(_) => break,
         }
     }
+    Ok(())
 }
 
-fn traverse_objects<R: Read>(graph: &mut Graph, parser: &mut Parser<R>) {
+fn traverse_objects<R: Read>(graph: &mut Graph, parser: &mut Parser<R>) -> Result<()> {
     loop {
-        match parser.next_event().expect("Failed to parse") {
+        match parser.next_event()? {
             Event::StartNode(node) => {
                 let is_pose = node.name() == "Pose";
-                let props = match ObjectProperties::from_attrs7400(node.attributes()) {
+                let props = match ObjectProperties::from_attrs7400(node.attributes())? {
                     Some(v) => v,
                     None => {
-                        parser.skip_current_node().unwrap();
+                        parser.skip_current_node()?;
                         continue;
                     }
                 };
                 if is_pose {
-                    traverse_pose(graph, parser, &props);
+                    traverse_pose(graph, parser, &props)?;
                 } else {
                     let node = create_object_node(&props);
                     graph.add_node(node);
-                    parser.skip_current_node().unwrap();
+                    parser.skip_current_node()?;
                 }
             }
             Event::EndNode => break,
             Event::EndFbx(_) => unreachable!(),
         }
     }
+    Ok(())
 }
 
-fn traverse_pose<R: Read>(graph: &mut Graph, parser: &mut Parser<R>, props: &ObjectProperties) {
+fn traverse_pose<R: Read>(
+    graph: &mut Graph,
+    parser: &mut Parser<R>,
+    props: &ObjectProperties,
+) -> Result<()> {
     let mut pose_type = String::new();
     loop {
-        match parser.next_event().expect("Failed to parse") {
+        match parser.next_event()? {
             Event::StartNode(node) => match node.name() {
                 "Type" => {
                     if let Some(AttributeValue::String(s)) =
-                        node.attributes().load_next(DirectLoader).unwrap()
+                        node.attributes().load_next(DirectLoader)?
                     {
                         pose_type = s;
                     }
-                    parser.skip_current_node().unwrap();
+                    parser.skip_current_node()?;
                 }
                 "PoseNode" => {
                     let mut child_id = None;
                     'pose_node: loop {
-                        match parser.next_event().expect("Failed to parse") {
+                        match parser.next_event()? {
                             Event::StartNode(node) => {
                                 if node.name() == "Node" {
                                     child_id = node
                                         .attributes()
-                                        .load_next(DirectLoader)
-                                        .unwrap()
+                                        .load_next(DirectLoader)?
                                         .and_then(|attr| attr.get_i64());
                                 }
-                                parser.skip_current_node().unwrap();
+                                parser.skip_current_node()?;
                             }
                             Event::EndNode => break 'pose_node,
                             Event::EndFbx(_) => unreachable!(),
@@ -85,7 +96,7 @@ fn traverse_pose<R: Read>(graph: &mut Graph, parser: &mut Parser<R>, props: &Obj
                         graph.add_edge(edge);
                     }
                 }
-                _ => parser.skip_current_node().unwrap(),
+                _ => parser.skip_current_node()?,
             },
             Event::EndNode => break,
             Event::EndFbx(_) => unreachable!(),
@@ -94,44 +105,62 @@ fn traverse_pose<R: Read>(graph: &mut Graph, parser: &mut Parser<R>, props: &Obj
     let _ = pose_type;
     let node = create_object_node(props);
     graph.add_node(node);
+    Ok(())
 }
 
-fn traverse_connections<R: Read>(graph: &mut Graph, parser: &mut Parser<R>) {
+fn traverse_connections<R: Read>(graph: &mut Graph, parser: &mut Parser<R>) -> Result<()> {
     loop {
-        match parser.next_event().expect("Failed to parse") {
+        match parser.next_event()? {
             Event::StartNode(node) => {
                 if node.name() != "C" {
-                    parser.skip_current_node().unwrap();
+                    parser.skip_current_node()?;
                     continue;
                 }
                 if let Some((connection_type, child_uid, parent_uid, property_name)) =
-                    load_connection(node.attributes())
+                    load_connection(node.attributes())?
                 {
                     let mut edge = Edge::new(parent_uid, child_uid);
                     edge.data.connection_type = Some(connection_type);
                     if let Some(prop_name) = property_name {
-                        edge.styles.insert("label".to_string(), prop_name.clone());
+                        edge.styles
+                            .insert("label".to_string(), escape_label_text(&prop_name));
                         edge.data.property_name = Some(prop_name);
                     }
                     graph.add_edge(edge);
                 }
-                parser.skip_current_node().unwrap();
+                parser.skip_current_node()?;
             }
             Event::EndNode => break,
             Event::EndFbx(_) => unreachable!(),
         }
     }
+    Ok(())
 }
 
-fn load_connection<R: Read>(
-    attrs: Attributes<'_, R>,
-) -> Option<(String, i64, i64, Option<String>)> {
+/// Connection type, child uid, parent uid and property name of a connection.
+type Connection = (String, i64, i64, Option<String>);
+
+/// Loads attributes of a connection, or returns `None` if they are not of a
+/// connection.
+fn load_connection<R: Read>(attrs: Attributes<'_, R>) -> Result<Option<Connection>> {
     let mut attrs = attrs.into_iter(std::iter::repeat(DirectLoader));
-    let connection_type = attrs.next()?.unwrap().get_string()?.to_owned();
-    let child_uid = attrs.next()?.unwrap().get_i64()?;
-    let parent_uid = attrs.next()?.unwrap().get_i64()?;
-    let property_name = attrs
-        .next()
-        .and_then(|attr| attr.unwrap().get_string().map(Into::into));
-    Some((connection_type, child_uid, parent_uid, property_name))
+    let mut next = || attrs.next().transpose();
+    let connection_type = match next()?.as_ref().and_then(|attr| attr.get_string()) {
+        Some(connection_type) => connection_type.to_owned(),
+        None => return Ok(None),
+    };
+    let (child_uid, parent_uid) = match (
+        next()?.and_then(|attr| attr.get_i64()),
+        next()?.and_then(|attr| attr.get_i64()),
+    ) {
+        (Some(child_uid), Some(parent_uid)) => (child_uid, parent_uid),
+        _ => return Ok(None),
+    };
+    let property_name = next()?.and_then(|attr| attr.get_string().map(Into::into));
+    Ok(Some((
+        connection_type,
+        child_uid,
+        parent_uid,
+        property_name,
+    )))
 }
@@ -0,0 +1,337 @@
+//! Variables in filter files, such as `${class}`.
+//!
+//! Variables are substituted in string values before filters are
+//! deserialized. In conditions, whose strings are regexes, and in connection
+//! types of `show` and `hide` targets, values are escaped so that they match
+//! literally, unless written as `${name:raw}`. `$$` is a literal `$`.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::fbx::check::JsonPath;
+
+/// Keys whose values are not strings.
+///
+/// If the whole value is a variable, its value is parsed as a JSON literal
+/// (such as `true` or `2`).
+const LITERAL_KEYS: &[&str] = &["show_implicit_nodes", "by_class", "min", "max"];
+
+/// Keys of attribute maps, where keys are attribute names.
+const ATTR_MAP_KEYS: &[&str] = &[
+    "attrs",
+    "styles",
+    "graph_styles",
+    "node_styles",
+    "edge_styles",
+    "cluster_styles",
+];
+
+/// Keys of sections which are not substituted.
+const VERBATIM_KEYS: &[&str] = &["include", "extends", "variables"];
+
+/// Substitutes variables in the filter file, adding problems such as undefined
+/// variables.
+///
+/// Returns whether anything is substituted.
+pub fn substitute(
+    value: &mut Value,
+    variables: &BTreeMap<String, String>,
+    problems: &mut Vec<(JsonPath, String)>,
+) -> bool {
+    let mut substituter = Substituter {
+        variables,
+        problems,
+        substituted: false,
+    };
+    if let Value::Object(map) = value {
+        for (key, value) in map {
+            if VERBATIM_KEYS.contains(&&key[..]) {
+                continue;
+            }
+            let context = Context::default().enter(key);
+            substituter.value(value, &JsonPath::default().key(key), context);
+        }
+    }
+    substituter.substituted
+}
+
+/// Where a value is.
+#[derive(Debug, Default, Clone, Copy)]
+struct Context<'a> {
+    /// Key of the value.
+    key: Option<&'a str>,
+    /// Whether the value is in a condition.
+    in_condition: bool,
+    /// Whether the value is in an attribute map.
+    in_attrs: bool,
+    /// Whether the value is in targets of `show` or `hide`.
+    in_targets: bool,
+}
+
+impl<'a> Context<'a> {
+    /// Returns the context of the member of the object.
+    fn enter(self, key: &'a str) -> Self {
+        Context {
+            key: Some(key),
+            in_condition: self.in_condition || key == "condition",
+            in_attrs: self.in_attrs || ATTR_MAP_KEYS.contains(&key),
+            in_targets: self.in_targets || key == "targets",
+        }
+    }
+}
+
+struct Substituter<'a> {
+    variables: &'a BTreeMap<String, String>,
+    problems: &'a mut Vec<(JsonPath, String)>,
+    substituted: bool,
+}
+
+impl Substituter<'_> {
+    fn value(&mut self, value: &mut Value, path: &JsonPath, context: Context<'_>) {
+        match value {
+            Value::String(s) => {
+                let is_literal =
+                    !context.in_attrs && context.key.is_some_and(|key| LITERAL_KEYS.contains(&key));
+                if let Some(new) = self.string(s, path, context) {
+                    self.substituted = true;
+                    *value = match serde_json::from_str(&new) {
+                        Ok(literal) if is_literal => literal,
+                        _ => Value::String(new),
+                    };
+                }
+            }
+            Value::Array(values) => {
+                for (i, value) in values.iter_mut().enumerate() {
+                    self.value(value, &path.index(i), context);
+                }
+            }
+            Value::Object(map) => {
+                // Arguments of legacy `show` and `hide` are targets.
+                let has_target_args = !map.contains_key("op")
+                    && matches!(
+                        map.get("name").and_then(Value::as_str),
+                        Some("show" | "hide")
+                    );
+                for (key, value) in map {
+                    let mut context = context.enter(key);
+                    context.in_targets |= has_target_args && key == "args";
+                    self.value(value, &path.key(key), context);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the substituted string, or `None` if nothing is substituted.
+    fn string(&mut self, s: &str, path: &JsonPath, context: Context<'_>) -> Option<String> {
+        if !s.contains('$') {
+            return None;
+        }
+        let mut result = String::with_capacity(s.len());
+        let mut rest = s;
+        let mut changed = false;
+        while let Some(pos) = rest.find('$') {
+            result.push_str(&rest[..pos]);
+            let tail = &rest[pos..];
+            if let Some(after) = tail.strip_prefix("$$") {
+                result.push('$');
+                rest = after;
+                changed = true;
+                continue;
+            }
+            let body = match tail.strip_prefix("${") {
+                Some(body) => body,
+                None => {
+                    result.push('$');
+                    rest = &tail[1..];
+                    continue;
+                }
+            };
+            let end = match body.find('}') {
+                Some(end) => end,
+                None => {
+                    self.problems
+                        .push((path.clone(), "unterminated variable `${`".to_owned()));
+                    return None;
+                }
+            };
+            let (name, raw) = match body[..end].split_once(':') {
+                Some((name, "raw")) => (name, true),
+                Some((name, modifier)) => {
+                    self.problems.push((
+                        path.clone(),
+                        format!(
+                            "unknown modifier `{}` of variable `{}` (expected `raw`)",
+                            modifier, name
+                        ),
+                    ));
+                    (name, true)
+                }
+                None => (&body[..end], false),
+            };
+            // Targets are `kind:depth:connection_type`, where only the
+            // connection type is a regex.
+            let escape =
+                context.in_condition || (context.in_targets && result.matches(':').count() >= 2);
+            match self.variables.get(name) {
+                Some(value) if escape && !raw => result.push_str(&regex::escape(value)),
+                Some(value) => result.push_str(value),
+                None => self.problems.push((
+                    path.clone(),
+                    format!(
+                        "undefined variable `{}` (set it by `--set {}=<value>`)",
+                        name, name
+                    ),
+                )),
+            }
+            rest = &body[end + 1..];
+            changed = true;
+        }
+        result.push_str(rest);
+        Some(result).filter(|_| changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Substitutes the variables, returning the value and the problems.
+    fn substituted(mut value: Value, variables: &[(&str, &str)]) -> (Value, Vec<String>) {
+        let variables = variables
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        let mut problems = Vec::new();
+        substitute(&mut value, &variables, &mut problems);
+        let problems = problems
+            .into_iter()
+            .map(|(path, message)| format!("{}: {}", path, message))
+            .collect();
+        (value, problems)
+    }
+
+    #[test]
+    fn dollars_and_modifiers() {
+        let value = json!({
+            "node_filters": [{ "condition": { "name": "^$$${x:raw}$" }, "operations": ["$$"] }]
+        });
+        let (value, problems) = substituted(value, &[("x", "Body.")]);
+        assert_eq!(
+            value,
+            json!({
+                "node_filters": [{ "condition": { "name": "^$Body.$" }, "operations": ["$"] }]
+            })
+        );
+        assert!(problems.is_empty());
+
+        let value = json!({ "node_filters": [{ "condition": { "name": "${x:lower}" } }] });
+        let (_, problems) = substituted(value, &[("x", "Body")]);
+        assert_eq!(
+            problems,
+            [
+                "node_filters[0].condition.name: unknown modifier `lower` of variable `x` \
+              (expected `raw`)"
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_in_conditions() {
+        let value = json!({
+            "node_operations": { "op": [{ "op": "set-label", "template": "${x}" }] },
+            "node_filters": [{ "condition": { "all": [{ "name": "^${x}$" }] } }],
+            "edge_filters": [{ "condition": { "src_condition": { "class": "${x}" } } }]
+        });
+        let (value, problems) = substituted(value, &[("x", "Body.(1)")]);
+        assert_eq!(
+            value,
+            json!({
+                "node_operations": { "op": [{ "op": "set-label", "template": "Body.(1)" }] },
+                "node_filters": [{ "condition": { "all": [{ "name": "^Body\\.\\(1\\)$" }] } }],
+                "edge_filters": [{ "condition": { "src_condition": { "class": "Body\\.\\(1\\)" } } }]
+            })
+        );
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn escaped_in_target_connection_types() {
+        let value = json!({
+            "node_operations": {
+                "tagged": [{ "op": "hide", "targets": ["${kind}:${depth}:^${ctype}$"] }],
+                "legacy": [{ "name": "show", "args": [["descendant::${ctype}"]] }],
+                "raw": [{ "op": "show", "targets": ["descendant::${ctype:raw}"] }]
+            }
+        });
+        let variables = [("kind", "descendant"), ("depth", "2"), ("ctype", "O.P")];
+        let (value, problems) = substituted(value, &variables);
+        assert_eq!(
+            value,
+            json!({
+                "node_operations": {
+                    "tagged": [{ "op": "hide", "targets": ["descendant:2:^O\\.P$"] }],
+                    "legacy": [{ "name": "show", "args": [["descendant::O\\.P"]] }],
+                    "raw": [{ "op": "show", "targets": ["descendant::O.P"] }]
+                }
+            })
+        );
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn literals() {
+        let value = json!({
+            "node_operations": {
+                "op": [
+                    { "op": "collapse", "by_class": "${yes}" },
+                    { "op": "update-attr", "attrs": { "max": "${n}" } }
+                ]
+            },
+            "node_filters": [{
+                "condition": { "in_degree": { "min": "${n}", "max": "${m}" } }
+            }],
+            "show_implicit_nodes": "${yes}"
+        });
+        let (value, problems) = substituted(value, &[("yes", "true"), ("n", "2"), ("m", "3")]);
+        assert_eq!(
+            value,
+            json!({
+                "node_operations": {
+                    "op": [
+                        { "op": "collapse", "by_class": true },
+                        { "op": "update-attr", "attrs": { "max": "2" } }
+                    ]
+                },
+                "node_filters": [{
+                    "condition": { "in_degree": { "min": 2, "max": 3 } }
+                }],
+                "show_implicit_nodes": true
+            })
+        );
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn undefined_variables() {
+        let value = json!({
+            "variables": { "x": "${verbatim}" },
+            "node_filters": [{ "condition": { "name": "a${x}b${y" } }],
+            "node_styles": { "color": "${color}" }
+        });
+        let (value, problems) = substituted(value, &[]);
+        assert_eq!(
+            problems,
+            [
+                "node_filters[0].condition.name: undefined variable `x` \
+                 (set it by `--set x=<value>`)",
+                "node_filters[0].condition.name: unterminated variable `${`",
+                "node_styles.color: undefined variable `color` (set it by `--set color=<value>`)",
+            ]
+        );
+        assert_eq!(value["variables"]["x"], "${verbatim}");
+    }
+}
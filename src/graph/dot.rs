@@ -0,0 +1,248 @@
+//! Escaping of identifiers and strings in DOT language.
+//!
+//! In DOT quoted strings, only `\"` and backslash-newline are handled by the
+//! lexer. Other backslash sequences are kept as is and interpreted later by
+//! "escString" attributes such as `label`, where `\n`, `\l` and `\r` are line
+//! breaks, `\N` and `\G` are the node and graph names, and `\\` is a backslash.
+
+/// Attributes whose values are interpreted as Graphviz "escString".
+const ESC_STRING_ATTRS: &[&str] = &[
+    "label",
+    "xlabel",
+    "headlabel",
+    "taillabel",
+    "tooltip",
+    "headtooltip",
+    "tailtooltip",
+    "edgetooltip",
+    "labeltooltip",
+];
+
+/// Characters which can follow a backslash as an intended escape sequence in
+/// escString attributes.
+const ESC_STRING_SEQUENCES: &[char] = &['n', 'l', 'r', 'N', 'G', 'E', 'H', 'T', 'L', '\\'];
+
+/// Returns the ID as is if it can be written unquoted, or a quoted string.
+pub fn id(raw: &str) -> String {
+    let mut chars = raw.chars();
+    let is_plain = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_plain {
+        raw.to_owned()
+    } else {
+        quote(raw)
+    }
+}
+
+/// Returns a quoted string of `raw`, which DOT parsers read without breaking
+/// the quotes.
+///
+/// Quotes are escaped, and other backslashes are kept as is, so most values
+/// (such as Windows paths) are read back unchanged. A backslash before a
+/// quote, a newline or the end is doubled so that it does not escape the
+/// following character, and is then read as two backslashes. Control
+/// characters other than tabs and newlines are replaced by visible symbols.
+pub fn quote(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len() + 2);
+    escaped.push('"');
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => match chars.peek() {
+                None | Some('"') | Some('\n') => escaped.push_str("\\\\"),
+                Some(_) => escaped.push('\\'),
+            },
+            c => push_visible(&mut escaped, c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Returns a quoted escString.
+///
+/// Backslash sequences meaningful in escStrings (such as `\n`) are kept, and
+/// other backslashes are escaped. Newline characters become `\n`.
+pub fn quote_esc_string(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len() + 2);
+    escaped.push('"');
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => match chars.peek() {
+                Some(&next) if ESC_STRING_SEQUENCES.contains(&next) => {
+                    escaped.push('\\');
+                    escaped.push(next);
+                    chars.next();
+                }
+                _ => escaped.push_str("\\\\"),
+            },
+            '\n' => escaped.push_str("\\n"),
+            c => push_visible(&mut escaped, c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Escapes text to be embedded literally into an escString value.
+///
+/// Use this for arbitrary strings (such as object names) composed into
+/// labels with intended escape sequences.
+pub fn escape_label_text(raw: &str) -> String {
+    raw.replace('\\', "\\\\")
+}
+
+/// Returns whether values of the attribute are interpreted as escString.
+pub fn is_esc_string_attr(key: &str) -> bool {
+    ESC_STRING_ATTRS.contains(&key)
+}
+
+/// Returns `key=value` with the key and the value quoted as necessary.
+pub fn attr(key: &str, value: &str) -> String {
+    let value = if is_esc_string_attr(key) {
+        quote_esc_string(value)
+    } else {
+        quote(value)
+    };
+    format!("{}={}", id(key), value)
+}
+
+/// Pushes the character, replacing control characters by visible symbols.
+fn push_visible(escaped: &mut String, c: char) {
+    match c {
+        '\t' | '\n' => escaped.push(c),
+        '\u{0}'..='\u{1f}' => {
+            escaped.push(std::char::from_u32(0x2400 + c as u32).expect("valid control picture"))
+        }
+        '\u{7f}' => escaped.push('\u{2421}'),
+        c if c.is_control() => escaped.push('\u{fffd}'),
+        c => escaped.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a quoted string as the Graphviz lexer does.
+    ///
+    /// `\"` is a quote, `\\` is kept as two backslashes, backslash-newline
+    /// is removed, and other characters are kept. Panics if the string is not
+    /// a single quoted string.
+    fn lex(quoted: &str) -> String {
+        let inner = quoted.strip_prefix('"').expect("should start with a quote");
+        let mut read = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    assert_eq!(chars.next(), None, "should end at the closing quote");
+                    return read;
+                }
+                '\\' => match chars.next() {
+                    Some('"') => read.push('"'),
+                    Some('\\') => read.push_str("\\\\"),
+                    Some('\n') => {}
+                    Some(c) => {
+                        read.push('\\');
+                        read.push(c);
+                    }
+                    None => panic!("unterminated string"),
+                },
+                c => read.push(c),
+            }
+        }
+        panic!("unterminated string");
+    }
+
+    /// Interprets an escString read by the lexer, with line breaks as `\n`.
+    fn interpret_esc_string(read: &str) -> String {
+        let mut text = String::new();
+        let mut chars = read.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') | Some('l') | Some('r') => text.push('\n'),
+                Some(c) => text.push(c),
+                None => text.push('\\'),
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn windows_path() {
+        let raw = r"C:\a\b";
+        assert_eq!(quote(raw), r#""C:\a\b""#);
+        assert_eq!(lex(&quote(raw)), raw);
+        assert_eq!(quote_esc_string(raw), r#""C:\\a\\b""#);
+        assert_eq!(interpret_esc_string(&lex(&quote_esc_string(raw))), raw);
+        let label = escape_label_text(raw);
+        assert_eq!(interpret_esc_string(&lex(&quote_esc_string(&label))), raw);
+    }
+
+    #[test]
+    fn trailing_backslash() {
+        let raw = r"a\";
+        // Doubled so that the closing quote is not escaped.
+        assert_eq!(quote(raw), r#""a\\""#);
+        assert_eq!(lex(&quote(raw)), r"a\\");
+        assert_eq!(interpret_esc_string(&lex(&quote_esc_string(raw))), raw);
+    }
+
+    #[test]
+    fn embedded_quote() {
+        let raw = r#"a"b"#;
+        assert_eq!(quote(raw), r#""a\"b""#);
+        assert_eq!(lex(&quote(raw)), raw);
+        assert_eq!(interpret_esc_string(&lex(&quote_esc_string(raw))), raw);
+    }
+
+    #[test]
+    fn backslash_quote() {
+        let raw = r#"a\"b"#;
+        // The backslash is doubled so that it does not escape the quote.
+        assert_eq!(quote(raw), r#""a\\\"b""#);
+        assert_eq!(lex(&quote(raw)), r#"a\\"b"#);
+        assert_eq!(interpret_esc_string(&lex(&quote_esc_string(raw))), raw);
+    }
+
+    #[test]
+    fn control_characters() {
+        let raw = "a\u{0}b\u{1}c";
+        assert_eq!(quote(raw), "\"a\u{2400}b\u{2401}c\"");
+        assert_eq!(quote_esc_string(raw), "\"a\u{2400}b\u{2401}c\"");
+        assert_eq!(lex(&quote(raw)), "a\u{2400}b\u{2401}c");
+    }
+
+    #[test]
+    fn literal_newline() {
+        let raw = "a\nb";
+        assert_eq!(quote(raw), "\"a\nb\"");
+        assert_eq!(lex(&quote(raw)), raw);
+        assert_eq!(quote_esc_string(raw), r#""a\nb""#);
+        assert_eq!(interpret_esc_string(&lex(&quote_esc_string(raw))), raw);
+        // A backslash before a newline is doubled, not a line continuation.
+        assert_eq!(lex(&quote("a\\\nb")), "a\\\\\nb");
+    }
+
+    #[test]
+    fn escaped_newline_in_name_used_as_label() {
+        // An object name containing a backslash and `n`, not a line break.
+        let name = r"Hips\nL";
+        let label = format!("{}\\n{}", escape_label_text(name), 100);
+        assert_eq!(attr("label", &label), r#"label="Hips\\nL\n100""#);
+        assert_eq!(
+            interpret_esc_string(&lex(&quote_esc_string(&label))),
+            "Hips\\nL\n100"
+        );
+    }
+}
@@ -1,15 +1,20 @@
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    hash::Hash,
     io::{self, Write},
     path::PathBuf,
 };
 
+pub mod dot;
+
 #[derive(Debug, Clone)]
 pub struct Graph<N: Clone, E: Clone> {
     pub name: PathBuf,
-    pub graph_styles: HashMap<String, String>,
-    pub node_styles: HashMap<String, String>,
-    pub edge_styles: HashMap<String, String>,
+    pub graph_styles: BTreeMap<String, String>,
+    pub node_styles: BTreeMap<String, String>,
+    pub edge_styles: BTreeMap<String, String>,
+    /// Styles of clusters, keyed by cluster names.
+    pub cluster_styles: BTreeMap<String, BTreeMap<String, String>>,
     pub nodes: BTreeMap<i64, Node<N>>,
     pub edges: Vec<Edge<E>>,
 }
@@ -21,6 +26,7 @@ impl<N: Clone, E: Clone> Graph<N, E> {
             graph_styles: Default::default(),
             node_styles: Default::default(),
             edge_styles: Default::default(),
+            cluster_styles: Default::default(),
             nodes: Default::default(),
             edges: Default::default(),
         }
@@ -34,137 +40,391 @@ impl<N: Clone, E: Clone> Graph<N, E> {
         self.edges.push(edge);
     }
 
-    pub fn map_ascendant<I, F>(&mut self, targets: I, fun: F)
+    /// Sorts edges by their ends, so that the output does not depend on the
+    /// order of connections in the source file.
+    ///
+    /// Edges with the same ends keep their relative order.
+    pub fn sort_edges(&mut self) {
+        self.edges.sort_by_key(|e| (e.parent, e.child));
+    }
+
+    /// Adds nodes and edges of the other graph into the cluster.
+    ///
+    /// Ids of the other graph are renumbered after the existing ones, so that
+    /// they do not collide. Nodes without labels are labelled by their
+    /// original ids (ends not registered as nodes are renumbered but not
+    /// labelled), and clusters of the other graph are renamed to
+    /// `cluster/name`. Styles of the graph are added unless already present.
+    pub fn merge(&mut self, other: Graph<N, E>, cluster: &str) {
+        let mut next_id = self.next_id();
+        let mut ids = BTreeMap::new();
+        let mut remap = |id: i64| {
+            *ids.entry(id).or_insert_with(|| {
+                next_id += 1;
+                next_id - 1
+            })
+        };
+
+        for (_, mut node) in other.nodes {
+            let old_id = node.id;
+            node.id = remap(old_id);
+            node.styles
+                .entry("label".to_owned())
+                .or_insert_with(|| old_id.to_string());
+            node.cluster = Some(match node.cluster {
+                Some(name) => format!("{}/{}", cluster, name),
+                None => cluster.to_owned(),
+            });
+            self.add_node(node);
+        }
+        for mut edge in other.edges {
+            edge.parent = remap(edge.parent);
+            edge.child = remap(edge.child);
+            self.add_edge(edge);
+        }
+        for (name, styles) in other.cluster_styles {
+            self.cluster_styles
+                .insert(format!("{}/{}", cluster, name), styles);
+        }
+        for (key, value) in other.graph_styles {
+            self.graph_styles.entry(key).or_insert(value);
+        }
+        for (key, value) in other.node_styles {
+            self.node_styles.entry(key).or_insert(value);
+        }
+        for (key, value) in other.edge_styles {
+            self.edge_styles.entry(key).or_insert(value);
+        }
+    }
+
+    /// Returns an id greater than the ids of all nodes and edge ends.
+    pub fn next_id(&self) -> i64 {
+        self.nodes
+            .keys()
+            .cloned()
+            .chain(self.edges.iter().flat_map(|e| [e.parent, e.child]))
+            .max()
+            .map_or(0, |id| id + 1)
+    }
+
+    /// Replaces the member nodes with the `summary` node, rerouting edges of
+    /// the members to it.
+    ///
+    /// Edges between members are removed, and rerouted edges which duplicate
+    /// the ends and the data of another edge are dropped.
+    pub fn collapse(&mut self, members: &[i64], summary: Node<N>)
     where
-        I: IntoIterator<Item = i64>,
-        F: Fn(&mut Node<N>),
+        E: Eq + Hash,
     {
-        let mut done = HashSet::new();
-        // Get parents of `targets`.
-        let mut undone_next = targets
-            .into_iter()
-            .flat_map(|i| {
-                self.edges
-                    .iter()
-                    .filter(|e| e.child == i)
-                    .map(|e| e.parent)
-                    .collect::<Vec<_>>()
-                    .into_iter()
-            })
-            .collect::<HashSet<i64>>();
-        loop {
-            let undone_current = undone_next;
-            undone_next = HashSet::new();
-            for target in undone_current {
-                if done.contains(&target) {
+        let members = members.iter().cloned().collect::<HashSet<i64>>();
+        for member in &members {
+            self.nodes.remove(member);
+        }
+        let summary_id = summary.id;
+        self.add_node(summary);
+
+        let reroute = |id: i64| {
+            if members.contains(&id) {
+                summary_id
+            } else {
+                id
+            }
+        };
+        let mut ends = self
+            .edges
+            .iter()
+            .filter(|e| !members.contains(&e.parent) && !members.contains(&e.child))
+            .map(|e| (e.parent, e.child, e.data.clone()))
+            .collect::<HashSet<_>>();
+        self.edges.retain_mut(|e| {
+            if !members.contains(&e.parent) && !members.contains(&e.child) {
+                return true;
+            }
+            if members.contains(&e.parent) && members.contains(&e.child) {
+                return false;
+            }
+            e.parent = reroute(e.parent);
+            e.child = reroute(e.child);
+            ends.insert((e.parent, e.child, e.data.clone()))
+        });
+    }
+
+    /// Removes the node, connecting each of its parents to each of its
+    /// children.
+    ///
+    /// `connect` creates an edge from the edge from a parent and the edge to a
+    /// child. New edges are not added if an edge with the same ends exists.
+    pub fn contract<F>(&mut self, id: i64, mut connect: F)
+    where
+        F: FnMut(&Edge<E>, &Edge<E>) -> Edge<E>,
+    {
+        self.nodes.remove(&id);
+        let (removed, kept): (Vec<_>, Vec<_>) = self
+            .edges
+            .drain(..)
+            .partition(|e| e.parent == id || e.child == id);
+        self.edges = kept;
+
+        let mut ends = self
+            .edges
+            .iter()
+            .map(|e| (e.parent, e.child))
+            .collect::<HashSet<_>>();
+        let from_parents = removed.iter().filter(|e| e.child == id && e.parent != id);
+        for from_parent in from_parents {
+            let to_children = removed.iter().filter(|e| e.parent == id && e.child != id);
+            for to_child in to_children {
+                if from_parent.parent == to_child.child {
                     continue;
                 }
-                // Process current node.
-                self.nodes.get_mut(&target).map(&fun);
-                // Queue parents of the `target`.
-                for parent in self
-                    .edges
-                    .iter()
-                    .filter(|e| e.child == target)
-                    .map(|e| e.parent)
-                    .filter(|p| !done.contains(p))
-                {
-                    undone_next.insert(parent);
+                if ends.insert((from_parent.parent, to_child.child)) {
+                    let edge = connect(from_parent, to_child);
+                    self.edges.push(edge);
                 }
-                done.insert(target);
-            }
-            if undone_next.is_empty() {
-                break;
             }
         }
     }
 
-    pub fn map_descendant<I, F>(&mut self, targets: I, fun: F)
+    /// Returns edges from the parents of the node.
+    pub fn parent_edges(&self, id: i64) -> impl Iterator<Item = &Edge<E>> {
+        self.edges.iter().filter(move |e| e.child == id)
+    }
+
+    /// Returns edges to the children of the node.
+    pub fn child_edges(&self, id: i64) -> impl Iterator<Item = &Edge<E>> {
+        self.edges.iter().filter(move |e| e.parent == id)
+    }
+
+    /// Returns an index of edges adjacent to each node.
+    ///
+    /// The index must be rebuilt after edges are added or removed.
+    pub fn adjacency(&self) -> Adjacency {
+        let mut adjacency = Adjacency::default();
+        for (i, e) in self.edges.iter().enumerate() {
+            adjacency.parents.entry(e.child).or_default().push(i);
+            adjacency.children.entry(e.parent).or_default().push(i);
+        }
+        adjacency
+    }
+
+    /// Returns edges from the parents of the node, looked up in the index.
+    pub fn indexed_parent_edges<'a>(
+        &'a self,
+        adjacency: &'a Adjacency,
+        id: i64,
+    ) -> impl Iterator<Item = &'a Edge<E>> {
+        adjacency.parent_edges(id).map(move |i| &self.edges[i])
+    }
+
+    /// Returns edges to the children of the node, looked up in the index.
+    pub fn indexed_child_edges<'a>(
+        &'a self,
+        adjacency: &'a Adjacency,
+        id: i64,
+    ) -> impl Iterator<Item = &'a Edge<E>> {
+        adjacency.child_edges(id).map(move |i| &self.edges[i])
+    }
+
+    /// Returns ids of ascendants of `targets`.
+    ///
+    /// Targets themselves are included only if they are reachable from
+    /// targets.
+    pub fn ascendants<I>(
+        &self,
+        targets: I,
+        traversal: &Traversal<'_, E>,
+        adjacency: &Adjacency,
+    ) -> BTreeSet<i64>
     where
         I: IntoIterator<Item = i64>,
-        F: Fn(&mut Node<N>),
     {
-        let mut done = HashSet::new();
-        // Get children of `targets`.
-        let mut undone_next = targets
-            .into_iter()
-            .flat_map(|i| {
-                self.edges
-                    .iter()
-                    .filter(|e| e.parent == i)
-                    .map(|e| e.child)
-                    .collect::<Vec<_>>()
-                    .into_iter()
-            })
-            .collect::<HashSet<i64>>();
-        loop {
-            let undone_current = undone_next;
-            undone_next = HashSet::new();
-            for target in undone_current {
-                if done.contains(&target) {
+        self.reachable(
+            targets,
+            traversal,
+            |id| adjacency.parent_edges(id),
+            |e| e.parent,
+        )
+    }
+
+    /// Returns ids of descendants of `targets`.
+    ///
+    /// Targets themselves are included only if they are reachable from
+    /// targets.
+    pub fn descendants<I>(
+        &self,
+        targets: I,
+        traversal: &Traversal<'_, E>,
+        adjacency: &Adjacency,
+    ) -> BTreeSet<i64>
+    where
+        I: IntoIterator<Item = i64>,
+    {
+        self.reachable(
+            targets,
+            traversal,
+            |id| adjacency.child_edges(id),
+            |e| e.child,
+        )
+    }
+
+    /// Returns ids reachable from `targets` by walking the edges given by
+    /// `edges_of` to the nodes given by `next_of`.
+    fn reachable<'a, I, F, J>(
+        &self,
+        targets: I,
+        traversal: &Traversal<'_, E>,
+        edges_of: F,
+        next_of: fn(&Edge<E>) -> i64,
+    ) -> BTreeSet<i64>
+    where
+        I: IntoIterator<Item = i64>,
+        F: Fn(i64) -> J,
+        J: Iterator<Item = usize> + 'a,
+    {
+        let mut found = BTreeSet::new();
+        let mut current = targets.into_iter().collect::<Vec<i64>>();
+        let mut depth = 0;
+        while !current.is_empty() && traversal.max_depth.is_none_or(|max| depth < max) {
+            let mut next = Vec::new();
+            for id in current {
+                for e in edges_of(id).map(|i| &self.edges[i]) {
+                    if traversal.follows(e) && found.insert(next_of(e)) {
+                        next.push(next_of(e));
+                    }
+                }
+            }
+            current = next;
+            depth += 1;
+        }
+        found
+    }
+
+    /// Returns the shortest path from any of `from` to any of `to`, following
+    /// edges from parents to children, as indices of the edges.
+    ///
+    /// The path is empty if `from` and `to` share a node, and `None` is
+    /// returned if `to` is unreachable.
+    pub fn shortest_path(&self, from: &BTreeSet<i64>, to: &BTreeSet<i64>) -> Option<Vec<usize>> {
+        if from.iter().any(|id| to.contains(id)) {
+            return Some(Vec::new());
+        }
+        let adjacency = self.adjacency();
+        // Edges by which nodes are reached first.
+        let mut reached_by = HashMap::<i64, usize>::new();
+        let mut visited = from.iter().cloned().collect::<HashSet<i64>>();
+        let mut queue = from.iter().cloned().collect::<VecDeque<i64>>();
+        while let Some(id) = queue.pop_front() {
+            for i in adjacency.child_edges(id) {
+                let child = self.edges[i].child;
+                if !visited.insert(child) {
                     continue;
                 }
-                // Process current node.
-                self.nodes.get_mut(&target).map(&fun);
-                // Queue children of the `target`.
-                for parent in self
-                    .edges
-                    .iter()
-                    .filter(|e| e.parent == target)
-                    .map(|e| e.child)
-                    .filter(|p| !done.contains(p))
-                {
-                    undone_next.insert(parent);
+                reached_by.insert(child, i);
+                if to.contains(&child) {
+                    let mut path = vec![i];
+                    let mut current = self.edges[i].parent;
+                    while let Some(&i) = reached_by.get(&current) {
+                        path.push(i);
+                        current = self.edges[i].parent;
+                    }
+                    path.reverse();
+                    return Some(path);
                 }
-                done.insert(target);
+                queue.push_back(child);
+            }
+        }
+        None
+    }
+
+    /// Returns paths from any of `from` to any of `to` which visit no node
+    /// twice, following edges from parents to children, as indices of the
+    /// edges, and whether the search stopped at the budget.
+    ///
+    /// Paths are searched in order of length, so the `limit` shortest paths
+    /// are returned. Paths do not pass through nodes of `to`, nor through
+    /// nodes of `from` other than the start. At most `budget` partial paths
+    /// are searched, so fewer paths may be returned if the budget is
+    /// exhausted.
+    pub fn all_paths(
+        &self,
+        from: &BTreeSet<i64>,
+        to: &BTreeSet<i64>,
+        limit: usize,
+        budget: usize,
+    ) -> (Vec<Vec<usize>>, bool) {
+        let adjacency = self.adjacency();
+        let mut paths = Vec::new();
+        // Partial paths as a tree: the last edge and the partial path before
+        // it (`None` for the start).
+        let mut partials = Vec::<(usize, Option<usize>)>::new();
+        // Ends of partial paths to extend, in order of length.
+        let mut queue = VecDeque::<(i64, Option<usize>)>::new();
+        for &start in from {
+            if to.contains(&start) {
+                paths.push(Vec::new());
+            } else {
+                queue.push_back((start, None));
             }
-            if undone_next.is_empty() {
+        }
+        while let Some((id, partial)) = queue.pop_front() {
+            if paths.len() >= limit {
                 break;
             }
+            for i in adjacency.child_edges(id) {
+                let child = self.edges[i].child;
+                let on_path = partial_path_edges(&partials, partial)
+                    .map(|i| self.edges[i].parent)
+                    .chain(Some(id))
+                    .any(|id| id == child);
+                if on_path || from.contains(&child) {
+                    continue;
+                }
+                if partials.len() >= budget {
+                    paths.truncate(limit);
+                    return (paths, true);
+                }
+                partials.push((i, partial));
+                let extended = Some(partials.len() - 1);
+                if to.contains(&child) {
+                    let mut path = partial_path_edges(&partials, extended).collect::<Vec<_>>();
+                    path.reverse();
+                    paths.push(path);
+                } else {
+                    queue.push_back((child, extended));
+                }
+            }
         }
+        paths.truncate(limit);
+        (paths, false)
     }
 
-    pub fn map_parents<I, F>(&mut self, targets: I, fun: F)
-    where
+    pub fn map_ascendant<I, F>(
+        &mut self,
+        targets: I,
+        traversal: &Traversal<'_, E>,
+        adjacency: &Adjacency,
+        fun: F,
+    ) where
         I: IntoIterator<Item = i64>,
         F: Fn(&mut Node<N>),
     {
-        // Get parents of `targets`.
-        let targets = targets
-            .into_iter()
-            .flat_map(|i| {
-                self.edges
-                    .iter()
-                    .filter(|e| e.child == i)
-                    .map(|e| e.parent)
-                    .collect::<Vec<_>>()
-                    .into_iter()
-            })
-            .collect::<Vec<i64>>();
-        for target in targets {
+        for target in self.ascendants(targets, traversal, adjacency) {
             // Process current node.
             self.nodes.get_mut(&target).map(&fun);
         }
     }
 
-    pub fn map_children<I, F>(&mut self, targets: I, fun: F)
-    where
+    pub fn map_descendant<I, F>(
+        &mut self,
+        targets: I,
+        traversal: &Traversal<'_, E>,
+        adjacency: &Adjacency,
+        fun: F,
+    ) where
         I: IntoIterator<Item = i64>,
         F: Fn(&mut Node<N>),
     {
-        // Get children of `targets`.
-        let targets = targets
-            .into_iter()
-            .flat_map(|i| {
-                self.edges
-                    .iter()
-                    .filter(|e| e.parent == i)
-                    .map(|e| e.child)
-                    .collect::<Vec<_>>()
-                    .into_iter()
-            })
-            .collect::<Vec<i64>>();
-        for target in targets {
+        for target in self.descendants(targets, traversal, adjacency) {
             // Process current node.
             self.nodes.get_mut(&target).map(&fun);
         }
@@ -173,11 +433,10 @@ impl<N: Clone, E: Clone> Graph<N, E> {
     pub fn output_all<W: Write>(&self, out: &mut W) -> io::Result<()> {
         self.print_beginning(out)?;
         // Print nodes
-        for n in self.nodes.values() {
-            n.print(out)?;
-        }
-        // Print edges
-        for e in &self.edges {
+        self.print_nodes(out, self.nodes.values())?;
+        self.print_legend(out, self.nodes.values())?;
+        // Print edges, except ones hidden by edge operations.
+        for e in self.edges.iter().filter(|e| e.is_visible()) {
             e.print(out)?;
         }
         self.print_ending(out)?;
@@ -191,26 +450,40 @@ impl<N: Clone, E: Clone> Graph<N, E> {
     ) -> io::Result<()> {
         self.print_beginning(out)?;
         // Print visible nodes
-        for n in self.nodes.values().filter(|n| n.is_visible()) {
-            n.print(out)?;
-        }
+        self.print_nodes(out, self.nodes.values().filter(|n| n.is_visible()))?;
+        self.print_legend(out, self.nodes.values().filter(|n| n.is_visible()))?;
         // Print edges
-        for e in &self.edges {
-            let parent_is_visible = self.nodes.get(&e.parent).map(|n| n.is_visible());
-            let child_is_visible = self.nodes.get(&e.child).map(|n| n.is_visible());
-            if (parent_is_visible.is_some() || child_is_visible.is_some())
-                && (parent_is_visible.unwrap_or(print_unregistered_nodes)
-                    && child_is_visible.unwrap_or(print_unregistered_nodes))
-            {
-                e.print(out)?;
-            }
+        for e in self.visible_edges(print_unregistered_nodes) {
+            e.print(out)?;
         }
         self.print_ending(out)?;
         Ok(())
     }
 
+    /// Returns visible edges whose both ends are visible.
+    ///
+    /// Ends which are not registered as nodes are regarded as visible if
+    /// `print_unregistered_nodes` is true, but edges with no registered ends
+    /// are never returned.
+    pub fn visible_edges(&self, print_unregistered_nodes: bool) -> impl Iterator<Item = &Edge<E>> {
+        self.edges
+            .iter()
+            .filter(|e| e.is_visible())
+            .filter(move |e| {
+                let parent_is_visible = self.nodes.get(&e.parent).map(|n| n.is_visible());
+                let child_is_visible = self.nodes.get(&e.child).map(|n| n.is_visible());
+                (parent_is_visible.is_some() || child_is_visible.is_some())
+                    && (parent_is_visible.unwrap_or(print_unregistered_nodes)
+                        && child_is_visible.unwrap_or(print_unregistered_nodes))
+            })
+    }
+
     pub fn print_beginning<W: Write>(&self, out: &mut W) -> io::Result<()> {
-        writeln!(out, "digraph \"{}\" {{", self.name.display())?;
+        writeln!(
+            out,
+            "digraph {} {{",
+            dot::quote(&self.name.display().to_string())
+        )?;
 
         // Print graph settings.
         if !self.graph_styles.is_empty() {
@@ -220,7 +493,7 @@ impl<N: Clone, E: Clone> Graph<N, E> {
                 if print_comma {
                     write!(out, "\n, ")?;
                 }
-                write!(out, "\t\t{}=\"{}\"", style_escape(key), style_escape(value))?;
+                write!(out, "\t\t{}", dot::attr(key, value))?;
                 print_comma = true;
             }
             writeln!(out, "\n\t]")?;
@@ -234,7 +507,7 @@ impl<N: Clone, E: Clone> Graph<N, E> {
                 if print_comma {
                     write!(out, "\n, ")?;
                 }
-                write!(out, "\t\t{}=\"{}\"", style_escape(key), style_escape(value))?;
+                write!(out, "\t\t{}", dot::attr(key, value))?;
                 print_comma = true;
             }
             writeln!(out, "\n\t]")?;
@@ -248,7 +521,7 @@ impl<N: Clone, E: Clone> Graph<N, E> {
                 if print_comma {
                     write!(out, "\n, ")?;
                 }
-                write!(out, "\t\t{}=\"{}\"", style_escape(key), style_escape(value))?;
+                write!(out, "\t\t{}", dot::attr(key, value))?;
                 print_comma = true;
             }
             writeln!(out, "\n\t]")?;
@@ -256,17 +529,144 @@ impl<N: Clone, E: Clone> Graph<N, E> {
         Ok(())
     }
 
+    /// Prints nodes, grouping clustered nodes into `subgraph cluster_*` blocks.
+    pub fn print_nodes<'a, W, I>(&self, out: &mut W, nodes: I) -> io::Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a Node<N>>,
+        N: 'a,
+    {
+        let mut clusters = BTreeMap::<&str, Vec<&Node<N>>>::new();
+        for n in nodes {
+            match n.cluster {
+                Some(ref cluster) => clusters.entry(cluster).or_default().push(n),
+                None => n.print(out)?,
+            }
+        }
+        for (cluster, nodes) in clusters {
+            writeln!(
+                out,
+                "\tsubgraph {} {{",
+                dot::quote(&format!("cluster_{}", cluster))
+            )?;
+            let styles = self.cluster_styles.get(cluster);
+            let mut attrs = Vec::new();
+            if !styles.is_some_and(|styles| styles.contains_key("label")) {
+                attrs.push(dot::attr("label", &dot::escape_label_text(cluster)));
+            }
+            attrs.extend(
+                styles
+                    .into_iter()
+                    .flatten()
+                    .map(|(key, value)| dot::attr(key, value)),
+            );
+            writeln!(out, "\t\tgraph [{}]", attrs.join(", "))?;
+            for n in nodes {
+                write!(out, "\t")?;
+                n.print(out)?;
+            }
+            writeln!(out, "\t}}")?;
+        }
+        Ok(())
+    }
+
+    /// Prints the legend entries of the nodes as a cluster of filled nodes,
+    /// if any.
+    pub fn print_legend<'a, W, I>(&self, out: &mut W, nodes: I) -> io::Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a Node<N>>,
+        N: 'a,
+    {
+        let legend = nodes
+            .into_iter()
+            .filter_map(|n| n.legend.as_ref())
+            .collect::<BTreeSet<_>>();
+        if legend.is_empty() {
+            return Ok(());
+        }
+        writeln!(out, "\tsubgraph {} {{", dot::quote("cluster_legend"))?;
+        writeln!(out, "\t\tgraph [{}]", dot::attr("label", "Legend"))?;
+        for (i, (description, color)) in legend.into_iter().enumerate() {
+            writeln!(
+                out,
+                "\t\t{} [{}, {}, {}, {}]",
+                dot::quote(&format!("legend_{}", i)),
+                dot::attr("label", &dot::escape_label_text(description)),
+                dot::attr("shape", "box"),
+                dot::attr("style", "filled"),
+                dot::attr("fillcolor", color)
+            )?;
+        }
+        writeln!(out, "\t}}")?;
+        Ok(())
+    }
+
     pub fn print_ending<W: Write>(&self, out: &mut W) -> io::Result<()> {
         writeln!(out, "}}")?;
         Ok(())
     }
 }
 
+/// Returns edges of the partial path of [`Graph::all_paths`], from the last
+/// one.
+fn partial_path_edges(
+    partials: &[(usize, Option<usize>)],
+    mut partial: Option<usize>,
+) -> impl Iterator<Item = usize> + '_ {
+    std::iter::from_fn(move || {
+        let (edge, prev) = partials[partial?];
+        partial = prev;
+        Some(edge)
+    })
+}
+
+/// Indices of edges from the parents and to the children of each node, in
+/// the order of the edges.
+#[derive(Debug, Default, Clone)]
+pub struct Adjacency {
+    parents: HashMap<i64, Vec<usize>>,
+    children: HashMap<i64, Vec<usize>>,
+}
+
+impl Adjacency {
+    /// Returns indices of edges from the parents of the node.
+    pub fn parent_edges(&self, id: i64) -> impl Iterator<Item = usize> + '_ {
+        self.parents.get(&id).into_iter().flatten().cloned()
+    }
+
+    /// Returns indices of edges to the children of the node.
+    pub fn child_edges(&self, id: i64) -> impl Iterator<Item = usize> + '_ {
+        self.children.get(&id).into_iter().flatten().cloned()
+    }
+}
+
+/// Predicate on edges.
+pub type EdgePredicate<'a, E> = dyn Fn(&Edge<E>) -> bool + 'a;
+
+/// Options for walking ascendants or descendants.
+pub struct Traversal<'a, E: Clone> {
+    /// Maximum distance from the targets, or unlimited if `None`.
+    pub max_depth: Option<usize>,
+    /// Predicate for edges to follow. All edges are followed if `None`.
+    pub follow: Option<&'a EdgePredicate<'a, E>>,
+}
+
+impl<E: Clone> Traversal<'_, E> {
+    fn follows(&self, edge: &Edge<E>) -> bool {
+        self.follow.is_none_or(|follow| follow(edge))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Node<T: Clone> {
     pub id: i64,
     pub visible: bool,
-    pub styles: HashMap<String, String>,
+    /// Name of the cluster the node belongs to.
+    pub cluster: Option<String>,
+    /// Legend entry of the node: a description and a fill color.
+    pub legend: Option<(String, String)>,
+    pub styles: BTreeMap<String, String>,
     pub data: T,
 }
 
@@ -281,6 +681,8 @@ impl<T: Clone> Node<T> {
         Node {
             id,
             visible: true,
+            cluster: None,
+            legend: None,
             styles: Default::default(),
             data,
         }
@@ -295,7 +697,7 @@ impl<T: Clone> Node<T> {
                 if print_comma {
                     write!(out, ", ")?;
                 }
-                write!(out, "{}=\"{}\"", style_escape(key), style_escape(value))?;
+                write!(out, "{}", dot::attr(key, value))?;
                 print_comma = true;
             }
             write!(out, "]")?;
@@ -313,7 +715,8 @@ impl<T: Clone> Node<T> {
 pub struct Edge<T: Clone> {
     pub parent: i64,
     pub child: i64,
-    pub styles: HashMap<String, String>,
+    pub visible: bool,
+    pub styles: BTreeMap<String, String>,
     pub data: T,
 }
 
@@ -328,6 +731,7 @@ impl<T: Clone> Edge<T> {
         Edge {
             parent,
             child,
+            visible: true,
             styles: Default::default(),
             data,
         }
@@ -342,7 +746,7 @@ impl<T: Clone> Edge<T> {
                 if print_comma {
                     write!(out, ", ")?;
                 }
-                write!(out, "{}=\"{}\"", style_escape(key), style_escape(value))?;
+                write!(out, "{}", dot::attr(key, value))?;
                 print_comma = true;
             }
             write!(out, "]")?;
@@ -350,8 +754,181 @@ impl<T: Clone> Edge<T> {
         writeln!(out)?;
         Ok(())
     }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
 }
 
-fn style_escape(raw: &str) -> String {
-    raw.replace('"', "\\\"")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a graph with the edges, indexed in order.
+    ///
+    /// ```text
+    /// 1 -0-> 2 -2-> 4 -6-> 5
+    /// 1 -1-> 3 -3-> 4 -5-> 2 -4-> 3
+    /// ```
+    fn graph() -> Graph<(), ()> {
+        let mut graph = Graph::new("test");
+        for id in 1..=5 {
+            graph.add_node(Node::new(id));
+        }
+        for (parent, child) in [(1, 2), (1, 3), (2, 4), (3, 4), (2, 3), (4, 2), (4, 5)] {
+            graph.add_edge(Edge::new(parent, child));
+        }
+        graph
+    }
+
+    fn ids(ids: &[i64]) -> BTreeSet<i64> {
+        ids.iter().cloned().collect()
+    }
+
+    #[test]
+    fn shortest_path() {
+        let graph = graph();
+        assert_eq!(
+            graph.shortest_path(&ids(&[1]), &ids(&[5])),
+            Some(vec![0, 2, 6])
+        );
+        assert_eq!(graph.shortest_path(&ids(&[1, 2]), &ids(&[2])), Some(vec![]));
+        assert_eq!(graph.shortest_path(&ids(&[5]), &ids(&[1])), None);
+    }
+
+    #[test]
+    fn all_paths_in_order_of_length() {
+        let graph = graph();
+        assert_eq!(
+            graph.all_paths(&ids(&[1]), &ids(&[4]), 10, 100),
+            (vec![vec![0, 2], vec![1, 3], vec![0, 4, 3]], false)
+        );
+        assert_eq!(
+            graph.all_paths(&ids(&[1]), &ids(&[4]), 2, 100),
+            (vec![vec![0, 2], vec![1, 3]], false)
+        );
+        assert_eq!(
+            graph.all_paths(&ids(&[1]), &ids(&[1]), 10, 100),
+            (vec![vec![]], false)
+        );
+    }
+
+    #[test]
+    fn all_paths_skip_other_starts() {
+        let graph = graph();
+        assert_eq!(
+            graph.all_paths(&ids(&[1, 2]), &ids(&[4]), 10, 100),
+            (vec![vec![2], vec![1, 3], vec![4, 3]], false)
+        );
+    }
+
+    #[test]
+    fn all_paths_visit_no_node_twice() {
+        let graph = graph();
+        assert_eq!(
+            graph.all_paths(&ids(&[4]), &ids(&[3]), 10, 100),
+            (vec![vec![5, 4]], false)
+        );
+    }
+
+    #[test]
+    fn all_paths_stop_at_budget() {
+        let graph = graph();
+        assert_eq!(
+            graph.all_paths(&ids(&[1]), &ids(&[4]), 10, 1),
+            (vec![], true)
+        );
+        assert_eq!(
+            graph.all_paths(&ids(&[1]), &ids(&[4]), 10, 3),
+            (vec![vec![0, 2]], true)
+        );
+    }
+
+    /// Returns the ends and the data of the edges.
+    fn edge_list<E: Clone>(graph: &Graph<(), E>) -> Vec<(i64, i64, E)> {
+        graph
+            .edges
+            .iter()
+            .map(|e| (e.parent, e.child, e.data.clone()))
+            .collect()
+    }
+
+    /// Returns a graph where 2 and 3 are children of 1 and parents of 4.
+    fn collapse_graph() -> Graph<(), String> {
+        let mut graph = Graph::new("test");
+        for id in 1..=5 {
+            graph.add_node(Node::new(id));
+        }
+        let edges = [
+            (1, 2, "OO"),
+            (1, 3, "OO"),
+            (2, 3, "OO"),
+            (2, 4, "OP"),
+            (3, 4, "OP"),
+            (3, 4, "OO"),
+            (5, 2, "OO"),
+        ];
+        for (parent, child, data) in edges {
+            graph.add_edge(Edge::new_with_data(parent, child, data.to_owned()));
+        }
+        graph
+    }
+
+    #[test]
+    fn collapse_drops_edges_between_members() {
+        let mut graph = collapse_graph();
+        graph.collapse(&[2, 3], Node::new(6));
+        assert_eq!(
+            graph.nodes.keys().cloned().collect::<Vec<_>>(),
+            [1, 4, 5, 6]
+        );
+        assert!(graph.edges.iter().all(|e| e.parent != e.child));
+        assert!(graph
+            .edges
+            .iter()
+            .all(|e| ![2, 3].contains(&e.parent) && ![2, 3].contains(&e.child)));
+    }
+
+    #[test]
+    fn collapse_removes_duplicate_edges() {
+        let mut graph = collapse_graph();
+        graph.collapse(&[2, 3], Node::new(6));
+        // Rerouted edges are kept if their data differ.
+        assert_eq!(
+            edge_list(&graph),
+            [
+                (1, 6, "OO".to_owned()),
+                (6, 4, "OP".to_owned()),
+                (6, 4, "OO".to_owned()),
+                (5, 6, "OO".to_owned()),
+            ]
+        );
+    }
+
+    /// Contracts node 2, whose parents are 1 and 4 and whose children are 3
+    /// and 4.
+    fn contracted() -> Vec<(i64, i64, ())> {
+        let mut graph = graph();
+        graph.contract(2, |from_parent, to_child| {
+            Edge::new(from_parent.parent, to_child.child)
+        });
+        assert!(!graph.nodes.contains_key(&2));
+        edge_list(&graph)
+    }
+
+    #[test]
+    fn contract_skips_self_loops() {
+        let edges = contracted();
+        assert!(edges.contains(&(4, 3, ())));
+        assert!(!edges.contains(&(4, 4, ())));
+    }
+
+    #[test]
+    fn contract_adds_no_duplicate_edges() {
+        // `1 -> 3` already exists, so only `1 -> 4` and `4 -> 3` are added.
+        assert_eq!(
+            contracted(),
+            [(1, 3, ()), (3, 4, ()), (4, 5, ()), (1, 4, ()), (4, 3, ())]
+        );
+    }
 }
@@ -0,0 +1,119 @@
+//! Renders dot sources with a locally installed Graphviz.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Output formats rendered by Graphviz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RenderFormat {
+    Svg,
+    Png,
+    Pdf,
+}
+
+impl RenderFormat {
+    /// Returns the render format for the extension of the given path.
+    ///
+    /// Returns `None` if the output should be written as a dot source.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "svg" => Some(RenderFormat::Svg),
+            "png" => Some(RenderFormat::Png),
+            "pdf" => Some(RenderFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    /// Returns the format name passed to `dot -T`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RenderFormat::Svg => "svg",
+            RenderFormat::Png => "png",
+            RenderFormat::Pdf => "pdf",
+        }
+    }
+}
+
+impl fmt::Display for RenderFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Graphviz invocation settings.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    /// Path to the `dot` executable.
+    pub dot_path: PathBuf,
+    /// Layout engine (such as `sfdp` or `neato`), passed as `-K`.
+    pub layout: Option<String>,
+}
+
+impl Renderer {
+    pub fn new<T: Into<PathBuf>>(dot_path: T) -> Self {
+        Renderer {
+            dot_path: dot_path.into(),
+            layout: None,
+        }
+    }
+
+    /// Renders the dot source and writes the result to `output`.
+    pub fn render(&self, source: &[u8], format: RenderFormat, output: &Path) -> io::Result<()> {
+        let mut command = Command::new(&self.dot_path);
+        command.arg(format!("-T{}", format));
+        if let Some(ref layout) = self.layout {
+            command.arg(format!("-K{}", layout));
+        }
+        command
+            .arg("-o")
+            .arg(output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let mut child = command.spawn().map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Graphviz executable `{}` not found; install Graphviz or specify \
+                         its location with `--dot-path`",
+                        self.dot_path.display()
+                    ),
+                )
+            } else {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to run `{}`: {}", self.dot_path.display(), e),
+                )
+            }
+        })?;
+        // Write stdin on another thread while stderr is read, so that an early
+        // exit of `dot` (a broken pipe) does not hide its error message.
+        // Stdin is closed after writing so that `dot` sees the end of input.
+        let mut stdin = child.stdin.take().expect("stdin should be piped");
+        let (written, result) = thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(source));
+            let result = child.wait_with_output();
+            let written = writer.join().expect("writer thread should not panic");
+            (written, result)
+        });
+        let result = result?;
+        if !result.status.success() {
+            return Err(io::Error::other(format!(
+                "`{}` failed ({}): {}",
+                self.dot_path.display(),
+                result.status,
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+        written?;
+        Ok(())
+    }
+}
@@ -1,59 +1,651 @@
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Write},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    panic,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
+use crate::graphviz::{RenderFormat, Renderer};
+
+mod batch;
 mod fbx;
 pub mod graph;
+mod graphviz;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Graphviz dot
+    Dot,
+    /// Cypher `CREATE` statements for Neo4j
+    Cypher,
+    /// `nodes.csv` and `relationships.csv` for neo4j-admin import
+    Neo4jCsv,
+    /// Indented text tree of the object-object hierarchy
+    Tree,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PaletteKey {
+    /// Object class
+    Class,
+    /// Object class and subclass
+    #[value(name = "class+subclass")]
+    ClassSubclass,
+}
+
+impl OutputFormat {
+    /// Returns the extension of output files, or `None` for directories.
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Dot => Some("dot"),
+            OutputFormat::Cypher => Some("cypher"),
+            OutputFormat::Neo4jCsv => None,
+            OutputFormat::Tree => Some("txt"),
+        }
+    }
+}
+
+impl PaletteKey {
+    /// Returns the key template of `palette` filter operation.
+    fn template(self) -> &'static str {
+        match self {
+            PaletteKey::Class => "{class}",
+            PaletteKey::ClassSubclass => "{class} ({subclass})",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PathFormat {
+    /// A line per path
+    Text,
+    /// Graphviz dot of the highlighted paths
+    Dot,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Validate filter files and report all problems found
+    CheckFilter {
+        /// Filter file paths, merged in order as with `--filter`
+        #[clap(required = true)]
+        filters: Vec<PathBuf>,
+        /// Value of a variable in filter files
+        #[clap(long = "set", value_name = "NAME=VALUE", value_parser = parse_variable)]
+        set: Vec<(String, String)>,
+    },
+    /// Write a focused graph for each class and subclass in the FBX file,
+    /// with an index page
+    Batch {
+        /// FBX file path
+        fbx_path: PathBuf,
+        /// Output directory
+        #[clap(long = "output")]
+        output: PathBuf,
+        /// Also render each graph by Graphviz in the format
+        #[clap(long = "render", value_enum)]
+        render: Option<RenderFormat>,
+        /// Graphviz `dot` executable used to render the graphs
+        #[clap(long = "dot-path", default_value = "dot")]
+        dot_path: PathBuf,
+        /// Graphviz layout engine used to render the graphs
+        #[clap(long = "layout")]
+        layout: Option<String>,
+    },
+    /// Find connection paths between two objects
+    ///
+    /// Paths follow edges from parents to children, or from `to` to `from` if
+    /// there are none in that direction.
+    Path {
+        /// FBX file path
+        fbx_path: PathBuf,
+        /// Start objects: a uid, or a regex of names
+        #[clap(value_name = "FROM")]
+        from: String,
+        /// End objects: a uid, or a regex of names
+        #[clap(value_name = "TO")]
+        to: String,
+        /// Find all paths instead of the shortest one
+        #[clap(long = "all")]
+        all: bool,
+        /// Maximum number of paths found by `--all`
+        #[clap(long = "limit", default_value = "100")]
+        limit: usize,
+        /// Output format
+        #[clap(long = "format", value_enum, default_value = "text")]
+        format: PathFormat,
+        /// Output file path
+        ///
+        /// If the format is dot and the extension is `svg`, `png` or `pdf`,
+        /// the graph is rendered by Graphviz.
+        #[clap(long = "output")]
+        output: Option<PathBuf>,
+        /// Graphviz `dot` executable used to render the output
+        #[clap(long = "dot-path", default_value = "dot")]
+        dot_path: PathBuf,
+        /// Graphviz layout engine used to render the output
+        #[clap(long = "layout")]
+        layout: Option<String>,
+    },
+}
 
 #[derive(Debug, Parser)]
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct CliOpt {
-    /// FBX file path
-    #[clap(name = "fbx-name")]
-    fbx_path: PathBuf,
-    /// Output dot file path
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// FBX file paths, directories containing FBX files, or glob patterns
+    #[clap(name = "fbx-name", required = true)]
+    fbx_paths: Vec<PathBuf>,
+    /// Output file path
+    ///
+    /// If the format is dot and the extension is `svg`, `png` or `pdf`, the
+    /// graph is rendered by Graphviz. For `neo4j-csv`, this is a directory.
+    /// For multiple FBX files, this is a directory to write an output per file
+    /// into, unless `--merge` is given.
     #[clap(long = "output")]
     output: Option<PathBuf>,
-    /// Filter json file path
+    /// Merge multiple FBX files into a graph, with a cluster per file
+    #[clap(long = "merge")]
+    merge: bool,
+    /// Output format
+    #[clap(long = "format", value_enum, default_value = "dot")]
+    format: OutputFormat,
+    /// Identifier of the FBX file used by Neo4j exports (defaults to the graph name,
+    /// and is only allowed for a single file)
+    #[clap(long = "file-id", conflicts_with = "merge")]
+    file_id: Option<String>,
+    /// Maximum depth printed by `tree` format
+    #[clap(long = "max-depth")]
+    max_depth: Option<usize>,
+    /// Emit canonical output: edges are sorted by their ends and the graph is
+    /// named by the file name instead of the whole path
+    #[clap(long = "canonical")]
+    canonical: bool,
+    /// Filter file path (JSON, or YAML or TOML by the extension)
+    ///
+    /// If given multiple times, filters are merged in order: later files
+    /// override styles and operations, and their filters are applied later.
     #[clap(long = "filter")]
-    filter: Option<PathBuf>,
+    filter: Vec<PathBuf>,
+    /// Value of a variable in filter files, such as `--set class=Model`
+    ///
+    /// `${class}` in filter files is replaced by the value. In conditions, the
+    /// value is escaped to match literally, unless written as `${class:raw}`.
+    #[clap(long = "set", value_name = "NAME=VALUE", value_parser = parse_variable)]
+    set: Vec<(String, String)>,
+    /// Print the JSON Schema of filter files and exit
+    #[clap(long = "print-filter-schema", exclusive = true)]
+    print_filter_schema: bool,
+    /// Fill objects with distinct colors by the key, and add a legend
+    ///
+    /// This is applied before the filter, so the filter can override colors.
+    #[clap(long = "palette", value_enum)]
+    palette: Option<PaletteKey>,
+    /// Show only the objects with the uid or the names matching the regex, and
+    /// their neighborhood
+    ///
+    /// This is applied after the filter. If given multiple times, objects
+    /// matching any of them are focused.
+    #[clap(long = "focus", value_name = "UID|NAME-REGEX", value_parser = parse_focus)]
+    focus: Vec<fbx::focus::Pattern>,
+    /// Distance of ascendants shown by `--focus`
+    #[clap(long = "up", default_value = "1", requires = "focus")]
+    up: usize,
+    /// Distance of descendants shown by `--focus`
+    #[clap(long = "down", default_value = "1", requires = "focus")]
+    down: usize,
+    /// Graphviz `dot` executable used to render the output
+    #[clap(long = "dot-path", default_value = "dot")]
+    dot_path: PathBuf,
+    /// Graphviz layout engine used to render the output (such as `sfdp` or `neato`)
+    #[clap(long = "layout")]
+    layout: Option<String>,
 }
 
 fn main() {
     let opt = CliOpt::parse();
 
-    let mut src = BufReader::new(File::open(&opt.fbx_path).unwrap());
-    let mut out: BufWriter<_> = BufWriter::new(if let Some(ref out_path) = opt.output {
-        Box::new(File::create(out_path).unwrap()) as Box<dyn Write>
+    if let Some(Command::CheckFilter {
+        ref filters,
+        ref set,
+    }) = opt.command
+    {
+        let variables = set.iter().cloned().collect();
+        if let Err(diagnostics) = fbx::check::load_filters(filters, &variables) {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+    if let Some(Command::Batch {
+        ref fbx_path,
+        ref output,
+        render,
+        ref dot_path,
+        ref layout,
+    }) = opt.command
+    {
+        let mut renderer = Renderer::new(dot_path);
+        renderer.layout = layout.clone();
+        let batch = batch::Batch {
+            out_dir: output.clone(),
+            render: render.map(|format| (renderer, format)),
+        };
+        let graph = load_graph(fbx_path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        });
+        if let Err(e) = batch.run(&graph) {
+            eprintln!("Failed to write graphs into {}: {}", output.display(), e);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::Path {
+        ref fbx_path,
+        ref from,
+        ref to,
+        all,
+        limit,
+        format,
+        ref output,
+        ref dot_path,
+        ref layout,
+    }) = opt.command
+    {
+        let mut graph = load_graph(fbx_path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        });
+        let find_objects = |pattern: &str| match fbx::path::find_objects(&graph, pattern) {
+            Ok(found) if !found.is_empty() => found,
+            Ok(_) => {
+                eprintln!("No objects match `{}`", pattern);
+                process::exit(2);
+            }
+            Err(e) => {
+                eprintln!("Invalid regex `{}`: {}", pattern, e);
+                process::exit(2);
+            }
+        };
+        let (from, to) = (find_objects(from), find_objects(to));
+        let paths = fbx::path::find_paths(&graph, from, to, Some(limit).filter(|_| all));
+        if paths.truncated {
+            eprintln!("The search was stopped at its budget, so some paths may be missing");
+        }
+        if paths.paths.is_empty() {
+            eprintln!("No path found");
+            process::exit(1);
+        }
+        match format {
+            PathFormat::Text => paths
+                .write_text(&graph, &mut open_output(output.as_deref()))
+                .unwrap(),
+            PathFormat::Dot => {
+                paths.highlight(&mut graph);
+                let mut dot_src = Vec::new();
+                graph.output_visible_nodes(&mut dot_src, true).unwrap();
+                let mut renderer = Renderer::new(dot_path);
+                renderer.layout = layout.clone();
+                write_dot_source(&dot_src, &renderer, output.as_deref());
+            }
+        }
+        return;
+    }
+    if opt.print_filter_schema {
+        let schema = fbx::filter::Filters::json_schema();
+        println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+        return;
+    }
+    if opt.merge && opt.format != OutputFormat::Dot {
+        eprintln!("`--merge` is supported only for `dot` format");
+        process::exit(2);
+    }
+    let fbx_paths = expand_inputs(&opt.fbx_paths);
+    let mut filters = if opt.filter.is_empty() {
+        None
     } else {
-        Box::new(::std::io::stdout()) as Box<dyn Write>
+        Some(load_filters(
+            &opt.filter,
+            &opt.set.iter().cloned().collect(),
+        ))
+    };
+    if !opt.focus.is_empty() {
+        // Print only visible nodes, as filters do.
+        filters.get_or_insert_with(Default::default);
+    }
+
+    if opt.file_id.is_some() && fbx_paths.len() > 1 {
+        eprintln!("`--file-id` cannot be used with multiple FBX files, which are identified by their names");
+        process::exit(2);
+    }
+
+    if opt.merge || fbx_paths.len() == 1 {
+        let graphs = map_parallel(&fbx_paths, |fbx_path| {
+            prepare_graph(&opt, fbx_path, filters.as_ref())
+        });
+        let graphs = graphs
+            .into_iter()
+            .filter_map(|graph| graph.map_err(|e| eprintln!("{}", e)).ok())
+            .collect::<Vec<_>>();
+        if graphs.len() < fbx_paths.len() {
+            process::exit(2);
+        }
+        if let [ref graph] = graphs[..] {
+            if !opt.merge {
+                write_output(&opt, graph, filters.as_ref(), opt.output.as_deref());
+                return;
+            }
+        }
+        let mut merged = fbx::Graph::new("merged");
+        let mut clusters = BTreeSet::new();
+        for graph in graphs {
+            let cluster = unique_name(graph.name.display().to_string(), &mut clusters);
+            merged.merge(graph, &cluster);
+        }
+        write_output(&opt, &merged, filters.as_ref(), opt.output.as_deref());
+    } else {
+        let out_dir = opt.output.as_ref().unwrap_or_else(|| {
+            eprintln!("`--output=<directory>` is required for multiple FBX files");
+            process::exit(2);
+        });
+        fs::create_dir_all(out_dir).unwrap();
+        let mut stems = BTreeSet::new();
+        let out_paths = fbx_paths
+            .iter()
+            .map(|fbx_path| {
+                let stem = fbx_path.file_stem().map_or_else(
+                    || "fbx".to_owned(),
+                    |stem| stem.to_string_lossy().into_owned(),
+                );
+                let stem = unique_name(stem, &mut stems);
+                match opt.format.extension() {
+                    Some(extension) => out_dir.join(format!("{}.{}", stem, extension)),
+                    None => out_dir.join(&stem),
+                }
+            })
+            .collect::<Vec<_>>();
+        let jobs = fbx_paths.iter().zip(&out_paths).collect::<Vec<_>>();
+        // Write each graph as soon as it is ready, not to keep all of them.
+        let results = map_parallel(&jobs, |&(fbx_path, out_path)| {
+            let graph = prepare_graph(&opt, fbx_path, filters.as_ref())?;
+            write_output(&opt, &graph, filters.as_ref(), Some(out_path));
+            Ok::<_, String>(())
+        });
+        // Other files are written even if some files cannot be read.
+        let mut failed = false;
+        for e in results.into_iter().filter_map(Result::err) {
+            eprintln!("{}", e);
+            failed = true;
+        }
+        if failed {
+            process::exit(2);
+        }
+    }
+}
+
+/// Applies the function to each item on as many threads as the available
+/// parallelism, and returns the results in the order of the items.
+fn map_parallel<T, U, F>(items: &[T], fun: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len());
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(i) {
+                            Some(item) => results.push((i, fun(item))),
+                            None => return results,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            // Panics of workers are already reported by them.
+            .flat_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect::<Vec<_>>()
     });
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
 
-    let mut graph = fbx::Graph::new(opt.fbx_path.clone());
+/// Returns the name, with `_` appended until it differs from the used ones.
+///
+/// Files in different directories can have the same name.
+fn unique_name(mut name: String, used: &mut BTreeSet<String>) -> String {
+    while !used.insert(name.clone()) {
+        name.push('_');
+    }
+    name
+}
 
-    // Add implicit root node.
-    graph.add_node(fbx::Node::new(0));
-
-    fbx::traverse(&mut graph, &mut src);
-
-    if let Some(ref filter_path) = opt.filter {
-        let filters: fbx::filter::Filters = {
-            use std::io::Read;
-            let mut filter_json_str = String::new();
-            File::open(filter_path)
-                .unwrap()
-                .read_to_string(&mut filter_json_str)
-                .unwrap();
-            serde_json::from_str(&filter_json_str).unwrap()
+/// Expands directories into FBX files in them, and glob patterns into matching
+/// files.
+///
+/// Exits if a directory or a pattern matches no files.
+fn expand_inputs(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let fail = |path: &Path, e: &dyn std::fmt::Display| -> ! {
+        eprintln!("Cannot read {}: {}", path.display(), e);
+        process::exit(2);
+    };
+    let mut expanded = Vec::new();
+    for path in paths {
+        let mut matched = if path.is_dir() {
+            fs::read_dir(path)
+                .unwrap_or_else(|e| fail(path, &e))
+                .map(|entry| entry.unwrap_or_else(|e| fail(path, &e)).path())
+                .filter(|path| {
+                    path.is_file()
+                        && path
+                            .extension()
+                            .is_some_and(|ext| ext.eq_ignore_ascii_case("fbx"))
+                })
+                .collect::<Vec<_>>()
+        } else if !path.exists() && path.to_string_lossy().contains(['*', '?', '[']) {
+            let pattern = path.to_string_lossy();
+            glob::glob(&pattern)
+                .unwrap_or_else(|e| {
+                    eprintln!("Invalid pattern `{}`: {}", pattern, e);
+                    process::exit(2);
+                })
+                .map(|entry| entry.unwrap_or_else(|e| fail(e.path(), e.error())))
+                .filter(|path| path.is_file())
+                .collect()
+        } else {
+            expanded.push(path.clone());
+            continue;
         };
+        if matched.is_empty() {
+            eprintln!("No FBX files found for `{}`", path.display());
+            process::exit(2);
+        }
+        matched.sort();
+        expanded.extend(matched);
+    }
+    expanded
+}
+
+/// Loads the FBX file, and applies the palette and the filters.
+fn prepare_graph(
+    opt: &CliOpt,
+    fbx_path: &Path,
+    filters: Option<&fbx::filter::Filters>,
+) -> Result<fbx::Graph, String> {
+    let mut graph = load_graph(fbx_path)?;
+
+    if let Some(key) = opt.palette {
+        fbx::filter::Filters::palette(key.template()).apply(&mut graph);
+    }
+    if let Some(filters) = filters {
         filters.apply(&mut graph);
-        graph
-            .output_visible_nodes(&mut out, filters.show_implicit_nodes.unwrap_or(false))
+    }
+    if !opt.focus.is_empty() {
+        let focus = fbx::focus::Focus {
+            patterns: opt.focus.clone(),
+            up: opt.up,
+            down: opt.down,
+        };
+        focus.apply(&mut graph);
+    }
+
+    if opt.canonical {
+        graph.sort_edges();
+        if let Some(file_name) = fbx_path.file_name() {
+            graph.name = file_name.into();
+        }
+    }
+    Ok(graph)
+}
+
+/// Writes the graph in the format to the output, or to the standard output.
+fn write_output(
+    opt: &CliOpt,
+    graph: &fbx::Graph,
+    filters: Option<&fbx::filter::Filters>,
+    output: Option<&Path>,
+) {
+    let print_unregistered_nodes = match filters {
+        Some(filters) => filters.show_implicit_nodes.unwrap_or(false),
+        None => true,
+    };
+    let file_id = opt
+        .file_id
+        .clone()
+        .unwrap_or_else(|| graph.name.display().to_string());
+
+    match opt.format {
+        OutputFormat::Dot => {
+            let mut dot_src = Vec::new();
+            write_dot(graph, filters, &mut dot_src).unwrap();
+            let mut renderer = Renderer::new(&opt.dot_path);
+            renderer.layout = opt.layout.clone();
+            write_dot_source(&dot_src, &renderer, output);
+        }
+        OutputFormat::Cypher => {
+            fbx::neo4j::write_cypher(
+                graph,
+                &file_id,
+                print_unregistered_nodes,
+                &mut open_output(output),
+            )
+            .unwrap();
+        }
+        OutputFormat::Neo4jCsv => {
+            let out_dir = output.unwrap_or_else(|| {
+                eprintln!("`--output=<directory>` is required for `neo4j-csv` format");
+                process::exit(2);
+            });
+            fbx::neo4j::write_csv(graph, &file_id, print_unregistered_nodes, out_dir).unwrap();
+        }
+        OutputFormat::Tree => {
+            fbx::tree::write_tree(
+                graph,
+                print_unregistered_nodes,
+                opt.max_depth,
+                &mut open_output(output),
+            )
             .unwrap();
+        }
+    }
+}
+
+/// Loads the FBX file as a graph.
+/// Loads the FBX file, or returns a message of the error.
+fn load_graph(fbx_path: &Path) -> Result<fbx::Graph, String> {
+    let cannot_read =
+        |e: &dyn std::fmt::Display| format!("Cannot read {}: {}", fbx_path.display(), e);
+    let mut src = BufReader::new(File::open(fbx_path).map_err(|e| cannot_read(&e))?);
+
+    let mut graph = fbx::Graph::new(fbx_path);
+
+    // Add implicit root node.
+    graph.add_node(fbx::Node::new(fbx::ROOT_UID));
+
+    fbx::traverse(&mut graph, &mut src).map_err(|e| cannot_read(&e))?;
+    Ok(graph)
+}
+
+/// Loads the filter files, or exits with diagnostics if they have problems.
+fn load_filters(
+    filter_paths: &[PathBuf],
+    variables: &BTreeMap<String, String>,
+) -> fbx::filter::Filters {
+    fbx::check::load_filters(filter_paths, variables).unwrap_or_else(|diagnostics| {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        process::exit(1);
+    })
+}
+
+/// Writes the dot source to the output, rendering it by Graphviz if the
+/// extension is `svg`, `png` or `pdf`.
+fn write_dot_source(dot_src: &[u8], renderer: &Renderer, output: Option<&Path>) {
+    match (output.and_then(RenderFormat::from_path), output) {
+        (Some(format), Some(out_path)) => {
+            if let Err(e) = renderer.render(dot_src, format, out_path) {
+                eprintln!("Failed to render {}: {}", out_path.display(), e);
+                process::exit(1);
+            }
+        }
+        _ => {
+            let mut out = open_output(output);
+            out.write_all(dot_src).unwrap();
+            out.flush().unwrap();
+        }
+    }
+}
+
+/// Parses `NAME=VALUE` of `--set`.
+fn parse_variable(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("expected `NAME=VALUE`, but got `{}`", s)),
+    }
+}
+
+/// Parses the pattern of `--focus`, which is a uid or a regex.
+fn parse_focus(s: &str) -> Result<fbx::focus::Pattern, String> {
+    s.parse().map_err(|e| format!("invalid regex: {}", e))
+}
+
+fn open_output(output: Option<&Path>) -> BufWriter<Box<dyn Write>> {
+    BufWriter::new(if let Some(out_path) = output {
+        Box::new(File::create(out_path).unwrap()) as Box<dyn Write>
+    } else {
+        Box::new(::std::io::stdout()) as Box<dyn Write>
+    })
+}
+
+fn write_dot<W: Write>(
+    graph: &fbx::Graph,
+    filters: Option<&fbx::filter::Filters>,
+    out: &mut W,
+) -> io::Result<()> {
+    if let Some(filters) = filters {
+        graph.output_visible_nodes(out, filters.show_implicit_nodes.unwrap_or(false))
     } else {
-        graph.output_all(&mut out).unwrap();
+        graph.output_all(out)
     }
 }